@@ -0,0 +1,337 @@
+use csv_indexer::{BTree, Error, Index, Result, Schema};
+use std::io::{self, Write};
+use std::process::ExitCode;
+
+pub const DEFAULT_ORDER: usize = 100;
+
+pub const USAGE: &str = "usage:
+    csv_indexer build <csv> [--column <name>] [--index <dir>] [--order <n>]
+    csv_indexer get <index> <key>...
+    csv_indexer range <index> <from> <to>
+    csv_indexer prefix <index> <prefix>
+    csv_indexer info <index>";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Build {
+        csv: String,
+        column: Option<String>,
+        index: String,
+        order: usize,
+    },
+    Get {
+        index: String,
+        key: Vec<String>,
+    },
+    Range {
+        index: String,
+        from: String,
+        to: String,
+    },
+    Prefix {
+        index: String,
+        prefix: String,
+    },
+    Info {
+        index: String,
+    },
+}
+
+fn parse_build(args: &[String]) -> std::result::Result<Command, String> {
+    let mut csv = None;
+    let mut column = None;
+    let mut index = None;
+    let mut order = DEFAULT_ORDER;
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("missing value for {}", flag))
+        };
+
+        match arg.as_str() {
+            "--column" => column = Some(value(arg)?),
+            "--index" => index = Some(value(arg)?),
+            "--order" => {
+                order = value(arg)?
+                    .parse()
+                    .ok()
+                    .filter(|order| *order >= 2)
+                    .ok_or_else(|| String::from("--order must be an integer of at least 2"))?
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+            _ if csv.is_none() => csv = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+
+    let csv = csv.ok_or_else(|| String::from("missing <csv>"))?;
+
+    Ok(Command::Build {
+        index: index.unwrap_or_else(|| format!("{}.index", csv)),
+        csv,
+        column,
+        order,
+    })
+}
+
+pub fn parse(args: &[String]) -> std::result::Result<Command, String> {
+    match args {
+        [command, rest @ ..] if command == "build" => parse_build(rest),
+        [command, index, key @ ..] if command == "get" && !key.is_empty() => Ok(Command::Get {
+            index: index.clone(),
+            key: key.to_vec(),
+        }),
+        [command, index, from, to] if command == "range" => Ok(Command::Range {
+            index: index.clone(),
+            from: from.clone(),
+            to: to.clone(),
+        }),
+        [command, index, prefix] if command == "prefix" => Ok(Command::Prefix {
+            index: index.clone(),
+            prefix: prefix.clone(),
+        }),
+        [command, index] if command == "info" => Ok(Command::Info {
+            index: index.clone(),
+        }),
+        [command, ..]
+            if ["build", "get", "range", "prefix", "info"].contains(&command.as_str()) =>
+        {
+            Err(format!("wrong arguments for {}", command))
+        }
+        [command, ..] => Err(format!("unknown command {}", command)),
+        [] => Err(String::from("missing command")),
+    }
+}
+
+fn print_lines<W: Write>(out: &mut W, lines: impl Iterator<Item = Result<String>>) -> Result<bool> {
+    let mut found = false;
+
+    for line in lines {
+        out.write_all(line?.as_bytes())?;
+        found = true;
+    }
+
+    Ok(found)
+}
+
+pub fn run<W: Write>(command: Command, out: &mut W) -> Result<bool> {
+    match command {
+        Command::Build {
+            csv,
+            column,
+            index,
+            order,
+        } => {
+            let schema = match column {
+                Some(column) => Schema::name(&column),
+                None => Schema::default(),
+            };
+
+            let (table, conflicts) = Index::build_reporting(&csv, &index, order, schema)?;
+
+            writeln!(
+                out,
+                "indexed {} keys from {} into {}",
+                table.tree().iter().count(),
+                csv,
+                index
+            )?;
+
+            for conflict in conflicts {
+                writeln!(out, "{}", conflict)?;
+            }
+
+            Ok(true)
+        }
+        Command::Get { index, key } => {
+            let mut table = Index::open(&index)?;
+
+            let key: Vec<&str> = key.iter().map(String::as_str).collect();
+            let lines = table.get(key.as_slice())?;
+            print_lines(out, lines)
+        }
+        Command::Range { index, from, to } => {
+            let mut table = Index::open(&index)?;
+
+            let lines = table.range(from.as_str()..=to.as_str())?;
+            print_lines(out, lines)
+        }
+        Command::Prefix { index, prefix } => {
+            let mut table = Index::open(&index)?;
+
+            let lines = table.prefix(&prefix)?;
+            print_lines(out, lines)
+        }
+        Command::Info { index } => {
+            let tree = BTree::load(&index)?;
+            let schema = tree.schema();
+
+            let columns: Vec<String> = schema
+                .columns
+                .iter()
+                .zip(&schema.kinds)
+                .map(|(column, kind)| format!("{} ({})", column, kind))
+                .collect();
+
+            writeln!(out, "index: {}", index)?;
+            writeln!(out, "order: {}", tree.order())?;
+            writeln!(out, "columns: {}", columns.join(", "))?;
+            writeln!(out, "keys: {}", tree.iter().count())?;
+
+            if let Some(source) = tree.source() {
+                let status = match tree.check_source() {
+                    Ok(_) => "fresh",
+                    Err(Error::Stale(_)) => "stale",
+                    Err(error) => return Err(error),
+                };
+
+                writeln!(out, "source: {}", source.path)?;
+                writeln!(out, "size: {}", source.size)?;
+                writeln!(out, "modified: {}", source.modified)?;
+                writeln!(out, "indexed: {}", source.offset)?;
+                writeln!(out, "status: {}", status)?;
+            }
+
+            Ok(true)
+        }
+    }
+}
+
+pub fn main(args: &[String]) -> ExitCode {
+    let command = match parse(args) {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("error: {}\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(command, &mut io::stdout().lock()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn _args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn _run(args: &[&str]) -> (Result<bool>, String) {
+        let mut out = Vec::new();
+        let result = super::run(super::parse(&_args(args)).unwrap(), &mut out);
+        (result, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            super::parse(&_args(&[
+                "build", "data.csv", "--column", "city", "--order", "4"
+            ])),
+            Ok(Command::Build {
+                csv: String::from("data.csv"),
+                column: Some(String::from("city")),
+                index: String::from("data.csv.index"),
+                order: 4,
+            })
+        );
+        assert_eq!(
+            super::parse(&_args(&["get", "idx", "BR", "2"])),
+            Ok(Command::Get {
+                index: String::from("idx"),
+                key: _args(&["BR", "2"]),
+            })
+        );
+        assert_eq!(
+            super::parse(&_args(&["range", "idx", "A", "C"])),
+            Ok(Command::Range {
+                index: String::from("idx"),
+                from: String::from("A"),
+                to: String::from("C"),
+            })
+        );
+        assert_eq!(
+            super::parse(&_args(&["prefix", "idx", "Li"])),
+            Ok(Command::Prefix {
+                index: String::from("idx"),
+                prefix: String::from("Li"),
+            })
+        );
+
+        assert!(super::parse(&[]).is_err());
+        assert!(super::parse(&_args(&["get", "idx"])).is_err());
+        assert!(super::parse(&_args(&["build", "data.csv", "--order", "1"])).is_err());
+        assert!(super::parse(&_args(&["build", "data.csv", "--index"])).is_err());
+        assert_eq!(
+            super::parse(&_args(&["drop", "idx"])),
+            Err(String::from("unknown command drop"))
+        );
+    }
+
+    #[test]
+    fn run() {
+        let index = "cli_test_run";
+
+        let (result, out) = _run(&[
+            "build",
+            "resources/header.csv",
+            "--column",
+            "city",
+            "--index",
+            index,
+            "--order",
+            "3",
+        ]);
+        assert!(result.unwrap());
+        assert_eq!(
+            out,
+            "indexed 3 keys from resources/header.csv into cli_test_run\n"
+        );
+
+        let (result, out) = _run(&["get", index, "Lisbon"]);
+        assert!(result.unwrap());
+        assert_eq!(out, "2,Ana,Lisbon\n");
+
+        let (result, out) = _run(&["get", index, "Faro"]);
+        assert!(!result.unwrap());
+        assert!(out.is_empty());
+
+        let (result, out) = _run(&["range", index, "Lisbon", "Porto"]);
+        assert!(result.unwrap());
+        assert_eq!(out, "2,Ana,Lisbon\n3,Carla,Porto\n");
+
+        let (result, out) = _run(&["prefix", index, "S"]);
+        assert!(result.unwrap());
+        assert_eq!(out, "1,Bruno,São Paulo\n");
+
+        let (result, out) = _run(&["info", index]);
+        assert!(result.unwrap());
+        assert!(out.starts_with("index: cli_test_run\norder: 3\ncolumns: city (string)\nkeys: 3\n"));
+        assert!(out.ends_with("status: fresh\n"));
+
+        assert!(matches!(
+            _run(&["build", "resources/header.csv", "--index", index]).0,
+            Err(Error::Io(_))
+        ));
+
+        fs::remove_dir_all(index).unwrap();
+
+        assert!(matches!(
+            _run(&["get", index, "Lisbon"]).0,
+            Err(Error::Io(_))
+        ));
+    }
+}
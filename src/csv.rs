@@ -1,51 +1,255 @@
-use crate::index::btree::BTree;
+use crate::error::{Error, Result};
+use crate::index::btree::{BTree, Conflict, DEFAULT_FILL_FACTOR};
 use crate::index::key::Key;
-use std::error;
+use crate::index::paged::Paged;
+use crate::index::schema::{Duplicates, Invalid, Schema};
+use crate::index::source::Source;
 use std::fs::File;
-use std::io::{prelude::*, BufReader, SeekFrom};
+use std::io::{self, prelude::*, BufReader, SeekFrom};
+use std::ops::RangeBounds;
 
-fn get_key(posic: usize, buf: &String) -> Option<&str> {
-    for (i, col) in buf.split(",").enumerate() {
-        if i == posic {
-            return Some(col);
+fn parse_record(record: &[u8]) -> Vec<String> {
+    let record = record.strip_suffix(b"\n").unwrap_or(record);
+    let record = record.strip_suffix(b"\r").unwrap_or(record);
+
+    let mut fields = Vec::new();
+    let mut field = Vec::new();
+    let mut quoted = false;
+    let mut bytes = record.iter().peekable();
+
+    while let Some(&byte) = bytes.next() {
+        match (quoted, byte) {
+            (true, b'"') if bytes.peek() == Some(&&b'"') => {
+                field.push(b'"');
+                bytes.next();
+            }
+            (true, b'"') => quoted = false,
+            (false, b'"') => quoted = true,
+            (false, b',') => {
+                fields.push(String::from_utf8_lossy(&field).into_owned());
+                field.clear();
+            }
+            (_, byte) => field.push(byte),
+        }
+    }
+
+    fields.push(String::from_utf8_lossy(&field).into_owned());
+    fields
+}
+
+fn get_key(columns: &[usize], buf: &[u8]) -> Option<Vec<String>> {
+    let mut fields = parse_record(buf);
+
+    columns
+        .iter()
+        .map(|posic| fields.get_mut(*posic).map(std::mem::take))
+        .collect()
+}
+
+fn read_record<R: BufRead>(reader: &mut R, buf: &mut Vec<u8>) -> io::Result<usize> {
+    let mut quoted = false;
+    buf.clear();
+
+    loop {
+        let start = buf.len();
+        let size = reader.read_until(b'\n', buf)?;
+
+        quoted = buf[start..]
+            .iter()
+            .fold(quoted, |quoted, byte| quoted ^ (*byte == b'"'));
+
+        if size == 0 || !quoted {
+            return Ok(buf.len());
         }
     }
-    None
 }
 
-pub fn index_file(file: &File, tree: &mut BTree) {
+const MAX_DIRTY_PAGES: usize = 65_536;
+
+fn keys<'a>(
+    file: &'a File,
+    schema: &'a Schema,
+    start: u64,
+) -> Result<impl Iterator<Item = Result<Key>> + 'a> {
     let mut reader = BufReader::new(file);
 
-    let mut buf = String::new();
+    let mut buf = Vec::new();
     let mut offset: u64 = 0;
 
-    loop {
-        buf.clear();
+    let header = match schema.header {
+        true => {
+            offset = read_record(&mut reader, &mut buf)? as u64;
+            Some(parse_record(&buf))
+        }
+        false => None,
+    };
 
-        let size: u64 = reader
-            .read_line(&mut buf)
-            .expect("reading from cursor shouldn't fail")
-            .try_into()
-            .unwrap();
+    let columns = schema.resolve(header.as_deref())?;
+
+    if start > offset {
+        offset = reader.seek(SeekFrom::Start(start))?;
+    }
+
+    Ok(std::iter::from_fn(move || loop {
+        let size = match read_record(&mut reader, &mut buf) {
+            Ok(size) => size as u64,
+            Err(e) => return Some(Err(e.into())),
+        };
 
         if size == 0 {
-            break;
+            return None;
         }
 
-        let key_value = match get_key(0, &buf) {
-            None => {
-                offset += size;
-                continue;
+        let position = (offset, size);
+        offset += size;
+
+        if buf.iter().all(|byte| *byte == b'\r' || *byte == b'\n') {
+            continue;
+        }
+
+        if let Some(key_value) = get_key(&columns, &buf) {
+            match (schema.parse(&key_value), schema.invalid) {
+                (Ok(value), _) => return Some(Ok(Key::composite(value, position))),
+                (Err(_), Invalid::Skip) => continue,
+                (Err(error), Invalid::Fail) => {
+                    return Some(Err(Error::Parse { offset: Some(position.0), error }))
+                }
             }
-            Some(value) => value,
-        };
+        }
+    }))
+}
 
-        tree.insert(Key::create(key_value, (offset, size)));
-        offset += size;
+pub fn index_file(file: &File, tree: &mut BTree) -> Result<Vec<Conflict>> {
+    index_from(file, tree, 0, None)
+}
+
+// Records how far into `source` the tree goes, in the same flush as the rows
+// before that offset.
+fn checkpoint(tree: &mut BTree, source: Option<&str>, offset: u64) -> Result<()> {
+    match source {
+        Some(path) => tree.set_source(Source {
+            offset,
+            ..Source::read_prefix(path, offset)?
+        }),
+        None => Ok(()),
     }
 }
 
-pub fn read_line(file: &mut File, position: (u64, u64)) -> Result<String, Box<dyn error::Error>> {
+// The run holds the exclusive lock throughout instead of switching around
+// every insert.
+fn index_from(
+    file: &File,
+    tree: &mut BTree,
+    start: u64,
+    source: Option<&str>,
+) -> Result<Vec<Conflict>> {
+    tree.batch(|tree| insert_from(file, tree, start, source))
+}
+
+// A run that fails partway keeps the rows it already inserted, and the
+// checkpoint written with them lets the next run resume right after them
+// instead of inserting them again.
+fn insert_from(
+    mut file: &File,
+    tree: &mut BTree,
+    start: u64,
+    source: Option<&str>,
+) -> Result<Vec<Conflict>> {
+    let schema = tree.schema().clone();
+    file.rewind()?;
+
+    if tree.is_empty() {
+        let conflicts = tree.bulk_load(keys(file, &schema, start)?, DEFAULT_FILL_FACTOR)?;
+        checkpoint(tree, source, file.stream_position()?)?;
+
+        return Ok(match schema.unique {
+            Some(Duplicates::Report) => conflicts,
+            _ => Vec::new(),
+        });
+    }
+
+    let mut keys = keys(file, &schema, start)?;
+    let write_back = tree.write_back();
+    tree.set_write_back(true)?;
+
+    let mut conflicts = Vec::new();
+    let mut offset = start;
+
+    let result = keys.try_for_each(|key| {
+        let key = key?;
+        let end = key.position.0 + key.position.1;
+
+        match tree.insert(key) {
+            Err(Error::Duplicate(conflict)) => match schema.unique {
+                Some(Duplicates::KeepLast) => {
+                    tree.replace(Key::composite(conflict.value, conflict.duplicate))?;
+                }
+                Some(Duplicates::Report) => conflicts.push(conflict),
+                Some(Duplicates::KeepFirst) => {}
+                _ => return Err(Error::Duplicate(conflict)),
+            },
+            result => result?,
+        }
+
+        offset = end;
+
+        if tree.dirty_pages() >= MAX_DIRTY_PAGES {
+            checkpoint(tree, source, offset)?;
+            tree.flush()?;
+        }
+
+        Ok(())
+    });
+
+    if result.is_ok() {
+        offset = file.stream_position()?;
+    }
+
+    let flushed = checkpoint(tree, source, offset)
+        .and_then(|_| tree.flush())
+        .and_then(|_| tree.set_write_back(write_back));
+
+    result?;
+    flushed?;
+    Ok(conflicts)
+}
+
+pub fn build(source: &str, tree: &mut BTree) -> Result<Vec<Conflict>> {
+    index_from(&File::open(source)?, tree, 0, Some(source))
+}
+
+pub fn index_file_incremental(source: &str, tree: &mut BTree) -> Result<Vec<Conflict>> {
+    let previous = match tree.source() {
+        Some(previous) => previous.clone(),
+        None if tree.is_empty() => return build(source, tree),
+        None => return Err(Error::NoSource(tree.path().to_string())),
+    };
+
+    previous.check_prefix(source)?;
+    index_from(&File::open(source)?, tree, previous.offset, Some(source))
+}
+
+pub fn open(path: &str, rebuild: bool) -> Result<BTree> {
+    let mut tree = BTree::load(path)?;
+
+    match (tree.check_source(), tree.source().cloned()) {
+        (Err(Error::Stale(_)), Some(source)) if rebuild => {
+            let file = File::open(&source.path)?;
+            let metadata = Source::read(&source.path)?;
+            let schema = tree.schema().clone();
+
+            tree.rebuild(keys(&file, &schema, 0)?, DEFAULT_FILL_FACTOR, || {
+                let offset = (&file).stream_position()?;
+                Ok(Source { offset, ..metadata })
+            })?;
+
+            Ok(tree)
+        }
+        (result, _) => result.map(|_| tree),
+    }
+}
+
+pub fn read_line(file: &mut File, position: (u64, u64)) -> Result<String> {
     let (start, offset) = position;
     file.seek(SeekFrom::Start(start))?;
 
@@ -53,15 +257,49 @@ pub fn read_line(file: &mut File, position: (u64, u64)) -> Result<String, Box<dy
     file.read_exact(&mut read_buf)?;
 
     match String::from_utf8(read_buf) {
-        Err(e) => Err(Box::new(e)),
+        Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e).into()),
         Ok(line) => Ok(line),
     }
 }
 
+pub fn read_keys<'a>(
+    file: &'a mut File,
+    keys: impl Iterator<Item = Result<Key>> + 'a,
+) -> impl Iterator<Item = Result<String>> + 'a {
+    keys.flat_map(|key| match key {
+        Ok(key) => key.positions().map(Ok).collect::<Vec<_>>(),
+        Err(e) => vec![Err(e)],
+    })
+    .map(move |position| read_line(file, position?))
+}
+
+pub fn read_range<'a, 'b, R: RangeBounds<&'b str>>(
+    file: &'a mut File,
+    tree: &'a BTree,
+    bounds: R,
+) -> Result<impl Iterator<Item = Result<String>> + 'a> {
+    tree.check_source()?;
+    Ok(read_keys(file, tree.range(bounds)?))
+}
+
+pub fn read_prefix<'a>(
+    file: &'a mut File,
+    tree: &'a BTree,
+    prefix: &'a str,
+) -> Result<impl Iterator<Item = Result<String>> + 'a> {
+    tree.check_source()?;
+    Ok(read_keys(file, tree.prefix(prefix)))
+}
+
+#[cfg(test)]
 mod tests {
 
     use crate::csv;
+    use crate::error::Error;
     use crate::index::btree::BTree;
+    use crate::index::key::Key;
+    use crate::index::schema::{Column, Duplicates, Schema};
+    use crate::index::value::Kind;
     use std::fs::File;
     use std::fs;
 
@@ -69,16 +307,25 @@ mod tests {
     fn get_key() {
         let buf = String::from("10,20,30");
 
-        match csv::get_key(1, &buf) {
+        match csv::get_key(&[1], buf.as_bytes()) {
             None => {
                 panic!("Key not found");
             }
             Some(key) => {
-                assert_eq!(key, "20");
+                assert_eq!(key, ["20"]);
             }
         };
 
-        match csv::get_key(3, &buf) {
+        match csv::get_key(&[2, 0], buf.as_bytes()) {
+            None => {
+                panic!("Key not found");
+            }
+            Some(key) => {
+                assert_eq!(key, ["30", "10"]);
+            }
+        };
+
+        match csv::get_key(&[0, 3], buf.as_bytes()) {
             None => {}
             Some(_) => {
                 panic!("Key shouldn't be found");
@@ -86,6 +333,54 @@ mod tests {
         };
     }
 
+    #[test]
+    fn parse_record() {
+        assert_eq!(csv::parse_record(b"10,20,30\n"), ["10", "20", "30"]);
+        assert_eq!(csv::parse_record(b"\"a,1\",,\"\"\r\n"), ["a,1", "", ""]);
+        assert_eq!(csv::parse_record(b"\"b \"\"q\"\"\",2"), ["b \"q\"", "2"]);
+        assert_eq!(csv::parse_record(b"\"c\nline\",3\n"), ["c\nline", "3"]);
+    }
+
+    #[test]
+    fn index_file_quoted() {
+
+        let filename = "resources/quoted.csv";
+        let tree_path  = "csv_test_index_file_quoted";
+
+        match File::open(filename) {
+            Err(_) => {panic!("Can't open {}!", filename);},
+            Ok(mut file) => {
+                let mut tree = BTree::create(3, tree_path).unwrap();
+
+                csv::index_file(&file, &mut tree).unwrap();
+
+                let positions: Vec<(String, (u64, u64))> = tree
+                    .iter()
+                    .map(|key| key.map(|key| (key.value[0].to_string(), key.position)).unwrap())
+                    .collect();
+
+                assert_eq!(positions, [
+                    (String::from("a,1"), (0, 10)),
+                    (String::from("b \"q\""), (10, 12)),
+                    (String::from("c\nline"), (22, 12)),
+                    (String::from("d"), (34, 4)),
+                    (String::from("e"), (39, 5)),
+                ]);
+
+                match tree.search("c\nline").unwrap() {
+                    None => {panic!("c\\nline not found!")},
+                    Some(key) => {
+                        match csv::read_line(&mut file, key.position) {
+                            Err(_) => {panic!("Can't read line c\\nline");},
+                            Ok(line) => {assert_eq!(line, "\"c\nline\",3\r\n")}
+                    }}
+                };
+            }
+        }
+
+        fs::remove_dir_all(tree_path).unwrap();
+    }
+
     #[test]
     fn index_file() {
 
@@ -95,21 +390,21 @@ mod tests {
         match File::open(filename) {
             Err(_) => {panic!("Can't open {}!", filename);},
             Ok(file) => {
-                let mut tree = BTree::create(3, tree_path);
+                let mut tree = BTree::create(3, tree_path).unwrap();
                 
-                csv::index_file(&file, &mut tree);
+                csv::index_file(&file, &mut tree).unwrap();
 
-                match tree.search("10") {
+                match tree.search("10").unwrap() {
                     None => {panic!("10 not found!")},
                     Some(key) => { assert_eq!(key.position, (0, 9))}
                 };
 
-                match tree.search("11") {
+                match tree.search("11").unwrap() {
                     None => {},
                     Some(_) => {panic!("Key shouldn't be found");}
                 };
 
-                match tree.search("20") {
+                match tree.search("20").unwrap() {
                     None => {panic!("20 not found!")},
                     Some(key) => { assert_eq!(key.position, (27, 6))}
                 };
@@ -119,6 +414,263 @@ mod tests {
         fs::remove_dir_all(tree_path).unwrap();
     }
 
+    #[test]
+    fn index_file_existing() {
+
+        let filename = "resources/sample.csv";
+        let tree_path  = "csv_test_index_file_existing";
+
+        match File::open(filename) {
+            Err(_) => {panic!("Can't open {}!", filename);},
+            Ok(file) => {
+                let mut tree = BTree::create(3, tree_path).unwrap();
+                tree.insert(Key::create("00", (0, 0))).unwrap();
+
+                csv::index_file(&file, &mut tree).unwrap();
+
+                assert_eq!(tree.iter().count(), 5);
+
+                match tree.search("70").unwrap() {
+                    None => {panic!("70 not found!")},
+                    Some(key) => { assert_eq!(key.position, (18, 9))}
+                };
+            }
+        }
+
+        fs::remove_dir_all(tree_path).unwrap();
+    }
+
+    #[test]
+    fn index_file_column() {
+
+        let filename = "resources/header.csv";
+        let tree_path  = "csv_test_index_file_column";
+
+        match File::open(filename) {
+            Err(_) => {panic!("Can't open {}!", filename);},
+            Ok(mut file) => {
+                let mut tree = BTree::create_with_schema(3, tree_path, Schema::name("city")).unwrap();
+
+                csv::index_file(&file, &mut tree).unwrap();
+
+                assert_eq!(tree.iter().count(), 3);
+                assert!(tree.search("city").unwrap().is_none());
+
+                match tree.search("Lisbon").unwrap() {
+                    None => {panic!("Lisbon not found!")},
+                    Some(key) => {
+                        match csv::read_line(&mut file, key.position) {
+                            Err(_) => {panic!("Can't read line Lisbon");},
+                            Ok(line) => {assert_eq!(line, "2,Ana,Lisbon\n")}
+                    }}
+                };
+
+                fs::remove_dir_all(tree_path).unwrap();
+
+                let mut tree = BTree::create_with_schema(3, tree_path, Schema::name("country")).unwrap();
+
+                match csv::index_file(&file, &mut tree) {
+                    Err(Error::ColumnNotFound(column)) => {assert_eq!(column, "country")},
+                    _ => {panic!("country shouldn't be found");}
+                };
+            }
+        }
+
+        fs::remove_dir_all(tree_path).unwrap();
+    }
+
+    #[test]
+    fn index_file_position() {
+
+        let filename = "resources/header.csv";
+        let tree_path  = "csv_test_index_file_position";
+
+        match File::open(filename) {
+            Err(_) => {panic!("Can't open {}!", filename);},
+            Ok(file) => {
+                let mut tree = BTree::create_with_schema(3, tree_path, Schema::position(1, true)).unwrap();
+
+                csv::index_file(&file, &mut tree).unwrap();
+
+                let names: Vec<String> = tree.iter().map(|key| key.unwrap().value[0].to_string()).collect();
+                assert_eq!(names, ["Ana", "Bruno", "Carla"]);
+
+                match tree.search("Bruno").unwrap() {
+                    None => {panic!("Bruno not found!")},
+                    Some(key) => { assert_eq!(key.position, (13, 19))}
+                };
+            }
+        }
+
+        fs::remove_dir_all(tree_path).unwrap();
+    }
+
+    #[test]
+    fn index_file_composite() {
+
+        let filename = "resources/header.csv";
+        let tree_path  = "csv_test_index_file_composite";
+
+        match File::open(filename) {
+            Err(_) => {panic!("Can't open {}!", filename);},
+            Ok(mut file) => {
+                let schema = Schema::composite(
+                    vec![Column::Name(String::from("city")), Column::Position(1)],
+                    true,
+                );
+                let mut tree = BTree::create_with_schema(3, tree_path, schema).unwrap();
+
+                csv::index_file(&file, &mut tree).unwrap();
+
+                assert_eq!(tree.iter().count(), 3);
+                assert!(tree.lookup("Lisbon").unwrap().is_none());
+                assert_eq!(tree.lookup_prefix("Lisbon").unwrap().count(), 1);
+
+                match tree.lookup(("Porto", "Carla")).unwrap() {
+                    None => {panic!("Porto, Carla not found!")},
+                    Some(key) => {
+                        match csv::read_line(&mut file, key.position) {
+                            Err(_) => {panic!("Can't read line Porto");},
+                            Ok(line) => {assert_eq!(line, "3,Carla,Porto\n")}
+                    }}
+                };
+            }
+        }
+
+        fs::remove_dir_all(tree_path).unwrap();
+    }
+
+    #[test]
+    fn index_file_typed() {
+
+        let filename = "resources/typed.csv";
+        let tree_path  = "csv_test_index_file_typed";
+
+        match File::open(filename) {
+            Err(_) => {panic!("Can't open {}!", filename);},
+            Ok(file) => {
+                let schema = Schema::name("id").typed(vec![Kind::Int]);
+                let mut tree = BTree::create_with_schema(3, tree_path, schema).unwrap();
+
+                match csv::index_file(&file, &mut tree) {
+                    Ok(_) => {panic!("x shouldn't parse as i64");},
+                    Err(e) => {assert_eq!(e.to_string(), "record at offset 52: can't parse \"x\" as i64")}
+                };
+
+                assert!(tree.is_empty());
+                drop(tree);
+                assert_eq!(BTree::load(tree_path).unwrap().iter().count(), 0);
+
+                fs::remove_dir_all(tree_path).unwrap();
+
+                let schema = Schema::name("id").typed(vec![Kind::Int]).skip_invalid();
+                let mut tree = BTree::create_with_schema(3, tree_path, schema).unwrap();
+
+                csv::index_file(&file, &mut tree).unwrap();
+
+                let ids: Vec<String> = tree.iter().map(|key| key.unwrap().value[0].to_string()).collect();
+                assert_eq!(ids, ["2", "10", "100"]);
+
+                fs::remove_dir_all(tree_path).unwrap();
+
+                let schema = Schema::name("date").typed(vec![Kind::Date]);
+                let mut tree = BTree::create_with_schema(3, tree_path, schema).unwrap();
+
+                csv::index_file(&file, &mut tree).unwrap();
+
+                let dates: Vec<u64> = tree.iter().map(|key| key.unwrap().position.0).collect();
+                assert_eq!(dates, [36, 69, 15, 52]);
+                assert_eq!(tree.range("2024-01-01".."2024-02-01").unwrap().count(), 2);
+            }
+        }
+
+        fs::remove_dir_all(tree_path).unwrap();
+    }
+
+    #[test]
+    fn index_file_duplicates() {
+
+        let filename = "resources/duplicates.csv";
+        let tree_path  = "csv_test_index_file_duplicates";
+
+        match File::open(filename) {
+            Err(_) => {panic!("Can't open {}!", filename);},
+            Ok(mut file) => {
+                let mut tree = BTree::create_with_schema(3, tree_path, Schema::name("city")).unwrap();
+
+                csv::index_file(&file, &mut tree).unwrap();
+
+                assert_eq!(tree.iter().count(), 2);
+                assert_eq!(tree.search_all("Lisbon").unwrap(), [(10, 11), (33, 13), (46, 14)]);
+
+                tree.insert(Key::create("Lisbon", (0, 10))).unwrap();
+                csv::index_file(&file, &mut tree).unwrap();
+
+                assert_eq!(tree.search_all("Lisbon").unwrap().len(), 4);
+                assert_eq!(tree.search_all("Porto").unwrap(), [(21, 12), (60, 10)]);
+
+                let lines: Vec<String> = csv::read_range(&mut file, &tree, "Porto"..).unwrap()
+                    .map(|line| line.unwrap())
+                    .collect();
+                assert_eq!(lines, ["Porto,Bruno\n", "Porto,Eva\n"]);
+            }
+        }
+
+        fs::remove_dir_all(tree_path).unwrap();
+    }
+
+    #[test]
+    fn index_file_unique() {
+
+        let filename = "resources/duplicates.csv";
+        let tree_path  = "csv_test_index_file_unique";
+
+        match File::open(filename) {
+            Err(_) => {panic!("Can't open {}!", filename);},
+            Ok(file) => {
+                for (duplicates, lisbon) in [
+                    (Duplicates::KeepFirst, (10, 11)),
+                    (Duplicates::KeepLast, (46, 14)),
+                    (Duplicates::Report, (10, 11)),
+                ] {
+                    let schema = Schema::name("city").unique(duplicates);
+                    let mut tree = BTree::create_with_schema(3, tree_path, schema).unwrap();
+
+                    let conflicts = csv::index_file(&file, &mut tree).unwrap();
+
+                    assert_eq!(tree.search_all("Lisbon").unwrap(), [lisbon]);
+                    assert_eq!(conflicts.len(), if duplicates == Duplicates::Report {3} else {0});
+
+                    tree.delete("Porto").unwrap();
+                    let conflicts = csv::index_file(&file, &mut tree).unwrap();
+
+                    assert_eq!(tree.search_all("Lisbon").unwrap(), [lisbon]);
+                    assert_eq!(tree.search_all("Porto").unwrap().len(), 1);
+                    assert_eq!(conflicts.len(), if duplicates == Duplicates::Report {4} else {0});
+
+                    fs::remove_dir_all(tree_path).unwrap();
+                }
+
+                let schema = Schema::name("city").unique(Duplicates::Fail);
+                let mut tree = BTree::create_with_schema(3, tree_path, schema).unwrap();
+
+                for _ in 0..2 {
+                    match csv::index_file(&file, &mut tree) {
+                        Ok(_) => {panic!("Lisbon should conflict");},
+                        Err(e) => {assert_eq!(e.to_string(), "duplicate key Lisbon at offset 33 (already indexed at offset 10)")}
+                    };
+
+                    assert!(tree.is_empty());
+                }
+
+                drop(tree);
+                assert_eq!(BTree::load(tree_path).unwrap().iter().count(), 0);
+            }
+        }
+
+        fs::remove_dir_all(tree_path).unwrap();
+    }
+
     #[test]
     fn read_line() {
         let filename = "resources/sample.csv";
@@ -128,10 +680,10 @@ mod tests {
             Err(_) => {panic!("Can't open {}!", filename);},
             Ok(mut file) => {
 
-                let mut tree = BTree::create(3, tree_path);
-                csv::index_file(& file, &mut tree);
+                let mut tree = BTree::create(3, tree_path).unwrap();
+                csv::index_file(& file, &mut tree).unwrap();
 
-                match tree.search("10") {
+                match tree.search("10").unwrap() {
                     None => {panic!("10 not found!")},
                     Some(key) => {
                         match csv::read_line(&mut file, key.position) {
@@ -144,4 +696,172 @@ mod tests {
 
         fs::remove_dir_all(tree_path).unwrap();
     }
+
+    #[test]
+    fn read_range() {
+        let filename = "resources/sample.csv";
+        let tree_path  = "csv_test_read_range";
+
+        match File::open(filename) {
+            Err(_) => {panic!("Can't open {}!", filename);},
+            Ok(mut file) => {
+
+                let mut tree = BTree::create(3, tree_path).unwrap();
+                csv::index_file(& file, &mut tree).unwrap();
+
+                let lines: Vec<String> = csv::read_range(&mut file, &tree, "10".."70").unwrap()
+                    .map(|line| line.unwrap())
+                    .collect();
+
+                assert_eq!(lines, ["10,20,30\n", "20,40\n", "40,50,60\n"]);
+            }
+        }
+
+        fs::remove_dir_all(tree_path).unwrap();
+    }
+
+    #[test]
+    fn build_stale() {
+        let source = "csv_test_build_stale.csv";
+        let tree_path  = "csv_test_build_stale";
+        fs::copy("resources/sample.csv", source).unwrap();
+
+        let mut tree = BTree::create(3, tree_path).unwrap();
+        csv::build(source, &mut tree).unwrap();
+        drop(tree);
+
+        let tree = csv::open(tree_path, false).unwrap();
+        assert!(tree.source().unwrap().path.ends_with(source));
+        assert_eq!(tree.iter().count(), 4);
+        drop(tree);
+
+        fs::write(source, "10,20,30\n90,80\n").unwrap();
+
+        match csv::open(tree_path, false) {
+            Err(Error::Stale(path)) => {assert!(path.ends_with(source))},
+            _ => {panic!("index should be stale");}
+        };
+
+        let tree = BTree::load(tree_path).unwrap();
+        let mut file = File::open(source).unwrap();
+
+        match csv::read_range(&mut file, &tree, ..) {
+            Err(Error::Stale(_)) => {},
+            _ => {panic!("index should be stale");}
+        };
+        drop(tree);
+
+        let tree = csv::open(tree_path, true).unwrap();
+        assert_eq!(tree.iter().count(), 2);
+        assert_eq!(tree.search("90").unwrap().unwrap().position, (9, 6));
+        assert!(tree.check_source().is_ok());
+        drop(tree);
+
+        fs::remove_file(source).unwrap();
+
+        match csv::open(tree_path, true) {
+            Err(Error::Io(_)) => {},
+            _ => {panic!("source shouldn't be found");}
+        };
+
+        assert!(BTree::load(tree_path).is_ok());
+
+        fs::remove_dir_all(tree_path).unwrap();
+    }
+
+    #[test]
+    fn index_file_incremental() {
+        let source = "csv_test_index_file_incremental.csv";
+        let tree_path  = "csv_test_index_file_incremental";
+        fs::write(source, "city,name\nLisbon,Ana\nPorto,Bruno\n").unwrap();
+
+        let mut tree = BTree::create_with_schema(3, tree_path, Schema::name("city")).unwrap();
+
+        csv::index_file_incremental(source, &mut tree).unwrap();
+        assert_eq!(tree.source().unwrap().offset, 33);
+        assert_eq!(tree.search_all("Lisbon").unwrap(), [(10, 11)]);
+
+        csv::index_file_incremental(source, &mut tree).unwrap();
+        assert_eq!(tree.iter().count(), 2);
+        assert_eq!(tree.search_all("Lisbon").unwrap().len(), 1);
+
+        fs::write(source, "city,name\nLisbon,Ana\nPorto,Bruno\nLisbon,Carla\nFaro,Duarte").unwrap();
+
+        csv::index_file_incremental(source, &mut tree).unwrap();
+        assert_eq!(tree.source().unwrap().offset, 57);
+        assert_eq!(tree.search_all("Lisbon").unwrap(), [(10, 11), (33, 13)]);
+        assert_eq!(tree.search_all("Faro").unwrap(), [(46, 11)]);
+        assert!(tree.check_source().is_ok());
+        drop(tree);
+
+        let mut tree = BTree::load(tree_path).unwrap();
+        fs::write(source, "city,name\nLisbon,Ana\nPorto,Bruno\nLisbon,Carla\nFaro,Duarte\nPorto,Eva\n").unwrap();
+
+        match csv::index_file_incremental(source, &mut tree) {
+            Err(Error::Changed(path)) => {assert!(path.ends_with(source))},
+            _ => {panic!("Faro,Duarte was extended");}
+        };
+
+        fs::write(source, "city,name\nLisbon,Ana\nPorto,Bruna\n").unwrap();
+
+        match csv::index_file_incremental(source, &mut tree) {
+            Err(e) => {assert!(e.to_string().ends_with("changed before the last indexed offset; rebuild the index"))},
+            Ok(_) => {panic!("Porto,Bruno was changed");}
+        };
+
+        assert_eq!(tree.iter().count(), 3);
+        drop(tree);
+        fs::remove_dir_all(tree_path).unwrap();
+
+        let mut tree = BTree::create_with_schema(3, tree_path, Schema::name("city")).unwrap();
+        csv::index_file(&File::open(source).unwrap(), &mut tree).unwrap();
+
+        match csv::index_file_incremental(source, &mut tree) {
+            Err(Error::NoSource(path)) => {assert_eq!(path, tree_path)},
+            _ => {panic!("index has no source to resume from");}
+        };
+
+        assert_eq!(tree.iter().count(), 2);
+
+        fs::remove_file(source).unwrap();
+        fs::remove_dir_all(tree_path).unwrap();
+    }
+
+    #[test]
+    fn index_file_incremental_retry() {
+        let source = "csv_test_index_file_incremental_retry.csv";
+        let tree_path  = "csv_test_index_file_incremental_retry";
+        fs::write(source, "id,name\n1,Ana\n2,Bruno\n").unwrap();
+
+        let schema = Schema::name("id").typed(vec![Kind::Int]).unique(Duplicates::Fail);
+        let mut tree = BTree::create_with_schema(3, tree_path, schema).unwrap();
+        csv::build(source, &mut tree).unwrap();
+
+        fs::write(source, "id,name\n1,Ana\n2,Bruno\n3,Carla\nx,Duarte\n4,Eva\n").unwrap();
+
+        // The retry hits the same row again rather than the ones before it.
+        for _ in 0..2 {
+            match csv::index_file_incremental(source, &mut tree) {
+                Err(Error::Parse { offset, .. }) => {assert_eq!(offset, Some(30))},
+                _ => {panic!("x isn't an integer");}
+            };
+
+            assert_eq!(tree.iter().count(), 3);
+            assert_eq!(tree.source().unwrap().offset, 30);
+        }
+
+        drop(tree);
+        let mut tree = BTree::load(tree_path).unwrap();
+        assert_eq!(tree.source().unwrap().offset, 30);
+
+        fs::write(source, "id,name\n1,Ana\n2,Bruno\n3,Carla\n5,Duarte\n4,Eva\n").unwrap();
+
+        assert!(csv::index_file_incremental(source, &mut tree).unwrap().is_empty());
+        assert_eq!(tree.iter().count(), 5);
+        assert_eq!(tree.search_all("3").unwrap(), [(22, 8)]);
+        assert!(tree.check_source().is_ok());
+
+        fs::remove_file(source).unwrap();
+        fs::remove_dir_all(tree_path).unwrap();
+    }
 }
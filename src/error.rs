@@ -0,0 +1,175 @@
+use crate::index::btree::Conflict;
+use crate::index::value::ParseError;
+use std::error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Corrupt(String),
+    VersionMismatch {
+        found: u8,
+        expected: u8,
+    },
+    Parse {
+        offset: Option<u64>,
+        error: ParseError,
+    },
+    ColumnNotFound(String),
+    KeyNotFound(String),
+    Stale(String),
+    Changed(String),
+    NoSource(String),
+    Duplicate(Conflict),
+    NotEmpty(String),
+    InvalidOrder(usize),
+    InvalidSchema(String),
+    Poisoned,
+    Locked {
+        path: String,
+        pid: Option<u32>,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Corrupt(reason) => write!(f, "corrupt index: {}", reason),
+            Error::VersionMismatch { found, expected } => write!(
+                f,
+                "unsupported format version {} (expected {})",
+                found, expected
+            ),
+            Error::Parse {
+                offset: Some(offset),
+                error,
+            } => write!(f, "record at offset {}: {}", offset, error),
+            Error::Parse {
+                offset: None,
+                error,
+            } => write!(f, "{}", error),
+            Error::ColumnNotFound(column) => write!(f, "key column {} not found", column),
+            Error::KeyNotFound(value) => write!(f, "key {} not found", value),
+            Error::Stale(path) => write!(f, "stale index: {} changed since it was indexed", path),
+            Error::Changed(path) => write!(
+                f,
+                "{} changed before the last indexed offset; rebuild the index",
+                path
+            ),
+            Error::NoSource(path) => write!(
+                f,
+                "index {} has no recorded source to resume from; rebuild the index",
+                path
+            ),
+            Error::Duplicate(conflict) => write!(f, "{}", conflict),
+            Error::NotEmpty(path) => write!(
+                f,
+                "index {} is not empty; bulk loading needs an empty index",
+                path
+            ),
+            Error::InvalidOrder(order) => {
+                write!(
+                    f,
+                    "invalid order {}: a tree needs an order of at least 2",
+                    order
+                )
+            }
+            Error::InvalidSchema(reason) => write!(f, "invalid schema: {}", reason),
+            Error::Poisoned => write!(f, "index lock poisoned by a panicked writer"),
+            Error::Locked {
+                path,
+                pid: Some(pid),
+            } => write!(f, "index {} locked by pid {}", path, pid),
+            Error::Locked { path, pid: None } => {
+                write!(f, "index {} locked by another process", path)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            Error::Parse { error, .. } => Some(error),
+            Error::Duplicate(conflict) => Some(conflict),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Error {
+        Error::Parse {
+            offset: None,
+            error,
+        }
+    }
+}
+
+impl From<Conflict> for Error {
+    fn from(conflict: Conflict) -> Error {
+        Error::Duplicate(conflict)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::value::Kind;
+
+    #[test]
+    fn display() {
+        let error = ParseError {
+            value: String::from("x"),
+            kind: Kind::Int,
+        };
+
+        assert_eq!(
+            Error::from(error.clone()).to_string(),
+            "can't parse \"x\" as i64"
+        );
+        assert_eq!(
+            Error::Parse {
+                offset: Some(52),
+                error
+            }
+            .to_string(),
+            "record at offset 52: can't parse \"x\" as i64"
+        );
+        assert_eq!(
+            Error::VersionMismatch {
+                found: 1,
+                expected: 6
+            }
+            .to_string(),
+            "unsupported format version 1 (expected 6)"
+        );
+        assert_eq!(
+            Error::ColumnNotFound(String::from("city")).to_string(),
+            "key column city not found"
+        );
+        assert_eq!(
+            Error::Stale(String::from("data.csv")).to_string(),
+            "stale index: data.csv changed since it was indexed"
+        );
+        assert_eq!(
+            Error::Locked {
+                path: String::from("idx/btree.idx"),
+                pid: Some(42)
+            }
+            .to_string(),
+            "index idx/btree.idx locked by pid 42"
+        );
+    }
+}
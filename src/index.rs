@@ -1,3 +1,18 @@
 pub mod key;
 pub mod node;
-pub mod btree;
\ No newline at end of file
+pub mod bplus;
+pub mod btree;
+pub mod bulk;
+pub mod cache;
+pub mod encoding;
+pub mod lock;
+pub mod paged;
+pub mod pager;
+pub mod range;
+pub mod schema;
+pub mod shared;
+pub mod snapshot;
+pub mod sort;
+pub mod source;
+pub mod value;
+pub mod wal;
\ No newline at end of file
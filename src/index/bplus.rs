@@ -0,0 +1,1072 @@
+use crate::error::{Error, Result};
+use crate::index::btree::Conflict;
+use crate::index::encoding::{write_varint, Reader, FORMAT_VERSION};
+use crate::index::key::{Components, Key};
+use crate::index::lock::{Lock, Wait, DEFAULT_WAIT};
+use crate::index::paged::{self, Metadata, Paged};
+use crate::index::pager::{Pager, META_PAGE};
+use crate::index::schema::Schema;
+use crate::index::value::Value;
+use std::ops::{Bound, RangeBounds};
+use std::sync::Mutex;
+
+// Internal nodes only hold separators to route a search: every key and its
+// positions live in a leaf, and each leaf points to the next one, so scans
+// walk the leaf chain instead of climbing back through the tree.
+#[derive(Clone)]
+pub struct Leaf {
+    pub keys: Vec<Key>,
+    pub next: u64,
+}
+
+#[derive(Clone)]
+pub struct Internal {
+    pub separators: Vec<Vec<Value>>,
+    pub children: Vec<u64>,
+}
+
+#[derive(Clone)]
+pub enum Content {
+    Leaf(Leaf),
+    Internal(Internal),
+}
+
+#[derive(Clone)]
+pub struct Node {
+    pub content: Content,
+    pub page: u64,
+}
+
+fn write_value(buf: &mut Vec<u8>, value: &[Value]) {
+    write_varint(buf, value.len() as u64);
+    value.iter().for_each(|component| component.encode(buf));
+}
+
+fn read_value(reader: &mut Reader) -> Result<Vec<Value>> {
+    (0..reader.read_varint()?)
+        .map(|_| Value::decode(reader))
+        .collect()
+}
+
+impl Node {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![FORMAT_VERSION];
+
+        match &self.content {
+            Content::Leaf(leaf) => {
+                buf.push(1);
+                write_varint(&mut buf, leaf.keys.len() as u64);
+                leaf.keys.iter().for_each(|key| key.encode(&mut buf));
+                write_varint(&mut buf, leaf.next);
+            }
+            Content::Internal(internal) => {
+                buf.push(0);
+                write_varint(&mut buf, internal.separators.len() as u64);
+                internal
+                    .separators
+                    .iter()
+                    .for_each(|separator| write_value(&mut buf, separator));
+                internal
+                    .children
+                    .iter()
+                    .for_each(|child| write_varint(&mut buf, *child));
+            }
+        }
+
+        buf
+    }
+
+    pub fn decode(page: u64, buf: &[u8]) -> Result<Node> {
+        let mut reader = Reader::create(buf);
+        reader.read_version()?;
+
+        let content = match reader.read_u8()? {
+            1 => Content::Leaf(Leaf {
+                keys: (0..reader.read_varint()?)
+                    .map(|_| Key::decode(&mut reader))
+                    .collect::<Result<_>>()?,
+                next: reader.read_varint()?,
+            }),
+            0 => {
+                let separators: Vec<Vec<Value>> = (0..reader.read_varint()?)
+                    .map(|_| read_value(&mut reader))
+                    .collect::<Result<_>>()?;
+                let children = (0..=separators.len())
+                    .map(|_| reader.read_varint())
+                    .collect::<Result<_>>()?;
+
+                Content::Internal(Internal {
+                    separators,
+                    children,
+                })
+            }
+            kind => {
+                return Err(Error::Corrupt(format!(
+                    "node at page {} has unknown kind {}",
+                    page, kind
+                )))
+            }
+        };
+
+        Ok(Node { content, page })
+    }
+
+    pub fn load(pager: &Pager, page: u64) -> Result<Node> {
+        Node::decode(page, &pager.read(page)?)
+    }
+
+    pub fn save(&self, pager: &mut Pager) -> Result<()> {
+        pager.write(self.page, &self.encode())
+    }
+
+    pub fn leaf(pager: &mut Pager) -> Result<Node> {
+        Ok(Node {
+            content: Content::Leaf(Leaf {
+                keys: Vec::new(),
+                next: 0,
+            }),
+            page: pager.allocate()?,
+        })
+    }
+
+    // The child whose range holds `value`: the one right of every separator
+    // that isn't greater than it.
+    fn child(internal: &Internal, value: &[Value]) -> u64 {
+        internal.children[internal
+            .separators
+            .partition_point(|separator| separator.as_slice() <= value)]
+    }
+
+    // Splits off the upper half when the node is over capacity, returning the
+    // separator and page the parent needs to route to the new right node.
+    fn split(&mut self, order: usize, pager: &mut Pager) -> Result<Option<(Vec<Value>, u64)>> {
+        let page = match &self.content {
+            Content::Leaf(leaf) if leaf.keys.len() > 2 * order - 1 => pager.allocate()?,
+            Content::Internal(internal) if internal.separators.len() > 2 * order - 1 => {
+                pager.allocate()?
+            }
+            _ => return Ok(None),
+        };
+
+        let (separator, content) = match &mut self.content {
+            Content::Leaf(leaf) => {
+                let keys = leaf.keys.split_off(leaf.keys.len() / 2);
+                let separator = keys[0].value.clone();
+                let next = std::mem::replace(&mut leaf.next, page);
+
+                (separator, Content::Leaf(Leaf { keys, next }))
+            }
+            Content::Internal(internal) => {
+                let mut separators = internal.separators.split_off(internal.separators.len() / 2);
+                let separator = separators.remove(0);
+                let children = internal.children.split_off(internal.separators.len() + 1);
+
+                (
+                    separator,
+                    Content::Internal(Internal {
+                        separators,
+                        children,
+                    }),
+                )
+            }
+        };
+
+        Node { content, page }.save(pager)?;
+        Ok(Some((separator, page)))
+    }
+
+    pub fn insert(
+        &mut self,
+        key: Key,
+        order: usize,
+        pager: &mut Pager,
+    ) -> Result<Option<(Vec<Value>, u64)>> {
+        match &mut self.content {
+            Content::Leaf(leaf) => {
+                match leaf
+                    .keys
+                    .binary_search_by(|existing| existing.value.cmp(&key.value))
+                {
+                    Ok(idx) => leaf.keys[idx].merge(&key),
+                    Err(idx) => leaf.keys.insert(idx, key),
+                }
+            }
+            Content::Internal(internal) => {
+                let idx = internal
+                    .separators
+                    .partition_point(|separator| *separator <= key.value);
+
+                let mut child = Node::load(pager, internal.children[idx])?;
+
+                match child.insert(key, order, pager)? {
+                    Some((separator, page)) => {
+                        internal.separators.insert(idx, separator);
+                        internal.children.insert(idx + 1, page);
+                    }
+                    None => return Ok(None),
+                }
+            }
+        }
+
+        let split = self.split(order, pager)?;
+        self.save(pager)?;
+        Ok(split)
+    }
+
+    // Keys in a leaf, separators in an internal node. Every node but the root
+    // keeps between t-1 and 2t-1 of them.
+    fn len(&self) -> usize {
+        match &self.content {
+            Content::Leaf(leaf) => leaf.keys.len(),
+            Content::Internal(internal) => internal.separators.len(),
+        }
+    }
+
+    pub fn delete(
+        &mut self,
+        value: &[Value],
+        order: usize,
+        pager: &mut Pager,
+    ) -> Result<Option<Key>> {
+        let key = match &mut self.content {
+            Content::Leaf(leaf) => {
+                match leaf
+                    .keys
+                    .binary_search_by(|key| key.value.as_slice().cmp(value))
+                {
+                    Ok(idx) => leaf.keys.remove(idx),
+                    Err(_) => return Ok(None),
+                }
+            }
+            Content::Internal(internal) => {
+                let idx = internal
+                    .separators
+                    .partition_point(|separator| separator.as_slice() <= value);
+
+                let mut child = Node::load(pager, internal.children[idx])?;
+
+                let key = match child.delete(value, order, pager)? {
+                    Some(key) => key,
+                    None => return Ok(None),
+                };
+
+                if child.len() >= order - 1 {
+                    return Ok(Some(key));
+                }
+
+                internal.fill(idx, child, order, pager)?;
+                key
+            }
+        };
+
+        self.save(pager)?;
+        Ok(Some(key))
+    }
+}
+
+fn mismatched(left: &Node, right: &Node) -> Error {
+    Error::Corrupt(format!(
+        "sibling nodes at pages {} and {} are of different kinds",
+        left.page, right.page
+    ))
+}
+
+impl Internal {
+    // Tops up the child at `idx` after a delete left it under t-1 entries,
+    // borrowing from a sibling that can spare one or merging with a sibling
+    // otherwise. Siblings share a parent, so the leaf chain only changes when
+    // a merge drops the right leaf out of it.
+    fn fill(&mut self, idx: usize, child: Node, order: usize, pager: &mut Pager) -> Result<()> {
+        let left = match idx {
+            0 => None,
+            _ => Some(Node::load(pager, self.children[idx - 1])?),
+        };
+        let right = match self.children.get(idx + 1) {
+            Some(page) => Some(Node::load(pager, *page)?),
+            None => None,
+        };
+
+        match (left, right) {
+            (Some(left), _) if left.len() >= order => self.borrow_left(idx, left, child, pager),
+            (_, Some(right)) if right.len() >= order => self.borrow_right(idx, child, right, pager),
+            (_, Some(right)) => self.merge(idx, child, right, pager),
+            (Some(left), None) => self.merge(idx - 1, left, child, pager),
+            // Only a root about to be collapsed has a single child.
+            (None, None) => Ok(()),
+        }
+    }
+
+    fn borrow_left(
+        &mut self,
+        idx: usize,
+        mut left: Node,
+        mut child: Node,
+        pager: &mut Pager,
+    ) -> Result<()> {
+        match (&mut left.content, &mut child.content) {
+            (Content::Leaf(sibling), Content::Leaf(leaf)) => {
+                leaf.keys.insert(0, sibling.keys.pop().unwrap());
+                self.separators[idx - 1] = leaf.keys[0].value.clone();
+            }
+            (Content::Internal(sibling), Content::Internal(internal)) => {
+                let separator = std::mem::replace(
+                    &mut self.separators[idx - 1],
+                    sibling.separators.pop().unwrap(),
+                );
+                internal.separators.insert(0, separator);
+                internal.children.insert(0, sibling.children.pop().unwrap());
+            }
+            _ => return Err(mismatched(&left, &child)),
+        }
+
+        left.save(pager)?;
+        child.save(pager)
+    }
+
+    fn borrow_right(
+        &mut self,
+        idx: usize,
+        mut child: Node,
+        mut right: Node,
+        pager: &mut Pager,
+    ) -> Result<()> {
+        match (&mut child.content, &mut right.content) {
+            (Content::Leaf(leaf), Content::Leaf(sibling)) => {
+                leaf.keys.push(sibling.keys.remove(0));
+                self.separators[idx] = sibling.keys[0].value.clone();
+            }
+            (Content::Internal(internal), Content::Internal(sibling)) => {
+                let separator =
+                    std::mem::replace(&mut self.separators[idx], sibling.separators.remove(0));
+                internal.separators.push(separator);
+                internal.children.push(sibling.children.remove(0));
+            }
+            _ => return Err(mismatched(&child, &right)),
+        }
+
+        child.save(pager)?;
+        right.save(pager)
+    }
+
+    // Folds the child right of the separator at `idx` into the one left of it
+    // and frees its page.
+    fn merge(&mut self, idx: usize, mut left: Node, right: Node, pager: &mut Pager) -> Result<()> {
+        match (&mut left.content, &right.content) {
+            (Content::Leaf(leaf), Content::Leaf(sibling)) => {
+                leaf.keys.extend(sibling.keys.iter().cloned());
+                leaf.next = sibling.next;
+                self.separators.remove(idx);
+            }
+            (Content::Internal(internal), Content::Internal(sibling)) => {
+                internal.separators.push(self.separators.remove(idx));
+                internal
+                    .separators
+                    .extend(sibling.separators.iter().cloned());
+                internal.children.extend(&sibling.children);
+            }
+            _ => return Err(mismatched(&left, &right)),
+        }
+
+        self.children.remove(idx + 1);
+        pager.free(right.page)?;
+        left.save(pager)
+    }
+}
+
+// Deletes rebalance on the way back up: a node left under t-1 entries borrows
+// from a sibling or merges with it, so merged pages go back to the pager and
+// the tree stays as shallow as its keys allow.
+pub struct BPlusTree {
+    root: Node,
+    order: usize,
+    schema: Schema,
+    pager: Pager,
+    lock: Mutex<Lock>,
+}
+
+impl Paged for BPlusTree {
+    type Saved = ();
+
+    fn pager(&self) -> &Pager {
+        &self.pager
+    }
+
+    fn pager_mut(&mut self) -> &mut Pager {
+        &mut self.pager
+    }
+
+    fn lock(&self) -> &Mutex<Lock> {
+        &self.lock
+    }
+
+    fn reload(&mut self) -> Result<()> {
+        self.root = Node::load(&self.pager, Metadata::load(&self.pager)?.root)?;
+        Ok(())
+    }
+
+    fn begin(&mut self) {}
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn restore(&mut self, _: ()) {}
+}
+
+impl BPlusTree {
+    fn filename(path: &str) -> String {
+        format!("{}/bplus.idx", path)
+    }
+
+    fn save(&mut self) -> Result<()> {
+        let metadata = Metadata {
+            root: self.root.page,
+            order: self.order,
+            schema: self.schema.clone(),
+            source: None,
+            retired: Vec::new(),
+        };
+
+        self.pager.write(META_PAGE, &metadata.encode())
+    }
+
+    pub fn load(path: &str) -> Result<BPlusTree> {
+        BPlusTree::load_with_wait(path, DEFAULT_WAIT)
+    }
+
+    pub fn load_with_wait(path: &str, wait: Wait) -> Result<BPlusTree> {
+        let (lock, pager, metadata) = paged::open(&BPlusTree::filename(path), wait)?;
+
+        let mut tree = BPlusTree {
+            root: Node::load(&pager, metadata.root)?,
+            order: metadata.order,
+            schema: metadata.schema,
+            pager,
+            lock: Mutex::new(lock),
+        };
+
+        tree.downgrade()?;
+        Ok(tree)
+    }
+
+    pub fn create(order: usize, path: &str) -> Result<BPlusTree> {
+        BPlusTree::create_with_schema(order, path, Schema::default())
+    }
+
+    pub fn create_with_schema(order: usize, path: &str, schema: Schema) -> Result<BPlusTree> {
+        let (lock, mut pager) = paged::create(path, &BPlusTree::filename(path), order, &schema)?;
+        let root = Node::leaf(&mut pager)?;
+        root.save(&mut pager)?;
+
+        let mut tree = BPlusTree {
+            root,
+            order,
+            schema,
+            pager,
+            lock: Mutex::new(lock),
+        };
+
+        tree.save()?;
+        tree.downgrade()?;
+        Ok(tree)
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn set_lock_wait(&mut self, wait: Wait) {
+        self.guard().set_wait(wait);
+    }
+
+    fn search(&self, value: &[Value]) -> Result<Option<Key>> {
+        let mut node = self.root.clone();
+
+        loop {
+            match node.content {
+                Content::Leaf(mut leaf) => {
+                    return Ok(leaf
+                        .keys
+                        .binary_search_by(|key| key.value.as_slice().cmp(value))
+                        .ok()
+                        .map(|idx| leaf.keys.swap_remove(idx)));
+                }
+                Content::Internal(internal) => {
+                    node = Node::load(&self.pager, Node::child(&internal, value))?;
+                }
+            }
+        }
+    }
+
+    pub fn insert(&mut self, key: Key) -> Result<()> {
+        if self.schema.unique.is_some() {
+            if let Some(existing) = self.search(&key.value)? {
+                return Err(Error::Duplicate(Conflict {
+                    value: key.value,
+                    existing: existing.position,
+                    duplicate: key.position,
+                }));
+            }
+        }
+
+        self.atomic(|tree| {
+            if let Some((separator, page)) = tree.root.insert(key, tree.order, &mut tree.pager)? {
+                tree.root = Node {
+                    content: Content::Internal(Internal {
+                        separators: vec![separator],
+                        children: vec![tree.root.page, page],
+                    }),
+                    page: tree.pager.allocate()?,
+                };
+
+                tree.root.save(&mut tree.pager)?;
+            }
+
+            tree.save()
+        })
+    }
+
+    pub fn lookup<C: Components>(&self, value: C) -> Result<Option<Key>> {
+        match self.schema.parse(&value.components()) {
+            Ok(value) => self.search(&value),
+            Err(_) => Ok(None),
+        }
+    }
+
+    pub fn delete<C: Components>(&mut self, value: C) -> Result<Key> {
+        let components = value.components();
+        let missing = || Error::KeyNotFound(components.join(","));
+
+        match self.schema.parse(&components) {
+            Ok(value) => self.atomic(|tree| tree.remove(&value))?.ok_or_else(missing),
+            Err(_) => Err(missing()),
+        }
+    }
+
+    fn remove(&mut self, value: &[Value]) -> Result<Option<Key>> {
+        let key = self.root.delete(value, self.order, &mut self.pager)?;
+
+        // A root whose last two children were merged hands the tree down to
+        // the one left.
+        if let Content::Internal(internal) = &self.root.content {
+            if internal.separators.is_empty() {
+                let child = internal.children[0];
+                self.pager.free(self.root.page)?;
+                self.root = Node::load(&self.pager, child)?;
+            }
+        }
+
+        self.save()?;
+        Ok(key)
+    }
+
+    pub fn lookup_prefix<C: Components>(&self, prefix: C) -> Result<LeafRange<'_>> {
+        let prefix = self.schema.parse(&prefix.components())?;
+
+        Ok(LeafRange::create(
+            &self.pager,
+            &self.root,
+            Bound::Included(prefix.clone()),
+            Bound::Included(prefix),
+        ))
+    }
+
+    pub fn range<'a, R: RangeBounds<&'a str>>(&self, bounds: R) -> Result<LeafRange<'_>> {
+        Ok(LeafRange::create(
+            &self.pager,
+            &self.root,
+            self.schema.parse_bound(bounds.start_bound())?,
+            self.schema.parse_bound(bounds.end_bound())?,
+        ))
+    }
+
+    pub fn iter(&self) -> LeafRange<'_> {
+        LeafRange::create(&self.pager, &self.root, Bound::Unbounded, Bound::Unbounded)
+    }
+}
+
+// Descends once to the leaf holding the start bound, then follows the leaf
+// chain; internal nodes are never read again.
+pub struct LeafRange<'a> {
+    pager: &'a Pager,
+    root: Option<Node>,
+    keys: std::vec::IntoIter<Key>,
+    next: u64,
+    start: Bound<Vec<Value>>,
+    end: Bound<Vec<Value>>,
+}
+
+impl<'a> LeafRange<'a> {
+    pub(crate) fn create(
+        pager: &'a Pager,
+        root: &Node,
+        start: Bound<Vec<Value>>,
+        end: Bound<Vec<Value>>,
+    ) -> LeafRange<'a> {
+        LeafRange {
+            pager,
+            root: Some(root.clone()),
+            keys: Vec::new().into_iter(),
+            next: 0,
+            start,
+            end,
+        }
+    }
+
+    // Composite bounds may be prefixes of the keys, so the descent stops left
+    // of the first separator the start bound could still be a prefix of.
+    fn descend(&mut self, mut node: Node) -> Result<()> {
+        loop {
+            match node.content {
+                Content::Leaf(leaf) => {
+                    self.next = leaf.next;
+                    self.keys = leaf.keys.into_iter();
+                    return Ok(());
+                }
+                Content::Internal(internal) => {
+                    let idx = match &self.start {
+                        Bound::Included(value) | Bound::Excluded(value) => {
+                            internal.separators.partition_point(|separator| {
+                                separator[..separator.len().min(value.len())] < value[..]
+                            })
+                        }
+                        Bound::Unbounded => 0,
+                    };
+
+                    node = Node::load(self.pager, internal.children[idx])?;
+                }
+            }
+        }
+    }
+
+    fn advance(&mut self) -> Result<Option<Key>> {
+        if let Some(root) = self.root.take() {
+            self.descend(root)?;
+        }
+
+        loop {
+            if let Some(key) = self.keys.next() {
+                return Ok(Some(key));
+            }
+
+            if self.next == 0 {
+                return Ok(None);
+            }
+
+            self.descend(Node::load(self.pager, self.next)?)?;
+        }
+    }
+
+    fn after_start(&self, key: &Key) -> bool {
+        match &self.start {
+            Bound::Included(value) => key.cmp_prefix(value).is_ge(),
+            Bound::Excluded(value) => key.cmp_prefix(value).is_gt(),
+            Bound::Unbounded => true,
+        }
+    }
+
+    fn before_end(&self, key: &Key) -> bool {
+        match &self.end {
+            Bound::Included(value) => key.cmp_prefix(value).is_le(),
+            Bound::Excluded(value) => key.cmp_prefix(value).is_lt(),
+            Bound::Unbounded => true,
+        }
+    }
+}
+
+impl Iterator for LeafRange<'_> {
+    type Item = Result<Key>;
+
+    fn next(&mut self) -> Option<Result<Key>> {
+        loop {
+            let key = match self.advance() {
+                Ok(Some(key)) => key,
+                Ok(None) => return None,
+                Err(error) => {
+                    self.keys = Vec::new().into_iter();
+                    self.next = 0;
+                    return Some(Err(error));
+                }
+            };
+
+            if !self.after_start(&key) {
+                continue;
+            }
+
+            if !self.before_end(&key) {
+                self.keys = Vec::new().into_iter();
+                self.next = 0;
+                return None;
+            }
+
+            return Some(Ok(key));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::schema::{Column, Duplicates};
+    use crate::index::value::Kind;
+    use std::fs;
+    use std::path::Path;
+
+    fn _create_key(value: &str) -> Key {
+        Key::create(value, (0, 0))
+    }
+
+    fn _values(range: LeafRange) -> Vec<String> {
+        range
+            .map(|key| {
+                let key = key.unwrap();
+                let value: Vec<String> = key.value.iter().map(|value| value.to_string()).collect();
+                value.join(",")
+            })
+            .collect()
+    }
+
+    // Checks separator bounds, that nodes other than the root hold between t-1
+    // and 2t-1 entries and that every leaf sits at the same depth, returning
+    // the leaves left to right.
+    fn _leaves(tree: &BPlusTree) -> Vec<u64> {
+        let mut leaves = Vec::new();
+        _check(
+            &tree.pager,
+            &tree.root,
+            tree.order,
+            true,
+            None,
+            None,
+            &mut leaves,
+        );
+        leaves
+    }
+
+    fn _check(
+        pager: &Pager,
+        node: &Node,
+        order: usize,
+        root: bool,
+        low: Option<&Vec<Value>>,
+        high: Option<&Vec<Value>>,
+        leaves: &mut Vec<u64>,
+    ) -> usize {
+        assert!(node.len() < 2 * order);
+        assert!(root || node.len() >= order - 1);
+
+        match &node.content {
+            Content::Leaf(leaf) => {
+                assert!(leaf
+                    .keys
+                    .windows(2)
+                    .all(|pair| pair[0].value < pair[1].value));
+                assert!(leaf
+                    .keys
+                    .iter()
+                    .all(|key| low.is_none_or(|low| &key.value >= low)
+                        && high.is_none_or(|high| &key.value < high)));
+                leaves.push(node.page);
+                1
+            }
+            Content::Internal(internal) => {
+                assert_eq!(internal.children.len(), internal.separators.len() + 1);
+                assert!(!internal.separators.is_empty());
+
+                let depths: Vec<usize> = internal
+                    .children
+                    .iter()
+                    .enumerate()
+                    .map(|(i, child)| {
+                        let low = match i {
+                            0 => low,
+                            _ => internal.separators.get(i - 1),
+                        };
+                        let high = internal.separators.get(i).or(high);
+                        let child = Node::load(pager, *child).unwrap();
+                        _check(pager, &child, order, false, low, high, leaves)
+                    })
+                    .collect();
+
+                assert!(depths.windows(2).all(|pair| pair[0] == pair[1]));
+                depths[0] + 1
+            }
+        }
+    }
+
+    fn _chain(pager: &Pager, first: u64) -> Vec<u64> {
+        let mut chain = Vec::new();
+        let mut next = first;
+
+        while next != 0 {
+            chain.push(next);
+            next = match Node::load(pager, next).unwrap().content {
+                Content::Leaf(leaf) => leaf.next,
+                Content::Internal(_) => panic!("internal node in the leaf chain"),
+            };
+        }
+
+        chain
+    }
+
+    #[test]
+    fn create_invalid() {
+        let path = "bplus_test_create_invalid";
+
+        assert!(matches!(
+            BPlusTree::create(0, path),
+            Err(Error::InvalidOrder(0))
+        ));
+        assert!(matches!(
+            BPlusTree::create_with_schema(3, path, Schema::composite(Vec::new(), false)),
+            Err(Error::InvalidSchema(_))
+        ));
+        assert!(!Path::new(path).exists());
+    }
+
+    #[test]
+    fn insert() {
+        let path = "bplus_test_insert";
+        let mut tree = BPlusTree::create(2, path).unwrap();
+        let values: Vec<String> = (0..200).map(|i| format!("{:03}", (i * 37) % 200)).collect();
+
+        for (i, value) in values.iter().enumerate() {
+            tree.insert(Key::create(value, (i as u64, 0))).unwrap();
+        }
+
+        tree.insert(Key::create("074", (500, 0))).unwrap();
+
+        let leaves = _leaves(&tree);
+        assert_eq!(_chain(&tree.pager, leaves[0]), leaves);
+        assert_eq!(tree.iter().count(), 200);
+        assert_eq!(tree.lookup("074").unwrap().unwrap().positions().count(), 2);
+        assert!(tree.lookup("200").unwrap().is_none());
+
+        drop(tree);
+
+        let tree = BPlusTree::load(path).unwrap();
+        assert_eq!(_values(tree.iter()), {
+            let mut sorted = values.clone();
+            sorted.sort();
+            sorted
+        });
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn range() {
+        let path = "bplus_test_range";
+        let mut tree = BPlusTree::create(2, path).unwrap();
+
+        for value in ["B", "D", "F", "H", "J", "L", "N"] {
+            tree.insert(_create_key(value)).unwrap();
+        }
+
+        assert_eq!(_values(tree.range("D".."J").unwrap()), ["D", "F", "H"]);
+        assert_eq!(
+            _values(tree.range("C"..="J").unwrap()),
+            ["D", "F", "H", "J"]
+        );
+        assert_eq!(_values(tree.range("K"..).unwrap()), ["L", "N"]);
+        assert_eq!(_values(tree.range(..="B").unwrap()), ["B"]);
+        assert!(tree.range("E".."F").unwrap().next().is_none());
+        assert_eq!(
+            _values(
+                tree.range((Bound::Excluded("B"), Bound::Excluded("F")))
+                    .unwrap()
+            ),
+            ["D"]
+        );
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn lookup_prefix() {
+        let path = "bplus_test_lookup_prefix";
+        let schema = Schema::composite(vec![Column::Position(0), Column::Position(1)], false)
+            .typed(vec![Kind::Str, Kind::UInt]);
+        let mut tree = BPlusTree::create_with_schema(2, path, schema).unwrap();
+
+        for (country, code) in [
+            ("AR", 1),
+            ("BR", 10),
+            ("BR", 2),
+            ("BR", 1),
+            ("CL", 1),
+            ("PT", 1),
+            ("PT", 2),
+        ] {
+            let value = vec![Value::from(country), Value::UInt(code)];
+            tree.insert(Key::composite(value, (0, 0))).unwrap();
+        }
+
+        assert_eq!(
+            _values(tree.lookup_prefix("BR").unwrap()),
+            ["BR,1", "BR,2", "BR,10"]
+        );
+        assert_eq!(_values(tree.lookup_prefix(("PT", "2")).unwrap()), ["PT,2"]);
+        assert!(tree.lookup_prefix("ES").unwrap().next().is_none());
+        assert!(tree.lookup(("CL", "1")).unwrap().is_some());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn scan() {
+        let path = "bplus_test_scan";
+        let mut tree = BPlusTree::create(2, path).unwrap();
+
+        for i in 0..100 {
+            tree.insert(_create_key(&format!("{:03}", i))).unwrap();
+        }
+
+        let leaves = _leaves(&tree).len() as u64;
+        let mut height = 0;
+        let mut node = tree.root.clone();
+
+        while let Content::Internal(internal) = node.content {
+            node = Node::load(&tree.pager, internal.children[0]).unwrap();
+            height += 1;
+        }
+
+        let before = tree.pager.cache_stats();
+        assert_eq!(tree.iter().count(), 100);
+        let after = tree.pager.cache_stats();
+
+        assert!(height > 1);
+        assert_eq!(
+            after.hits + after.misses - before.hits - before.misses,
+            leaves + height - 1
+        );
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn delete() {
+        let path = "bplus_test_delete";
+        let mut tree = BPlusTree::create(2, path).unwrap();
+
+        for i in 0..50 {
+            tree.insert(_create_key(&format!("{:02}", i))).unwrap();
+        }
+
+        for i in (0..50).step_by(2) {
+            tree.delete(format!("{:02}", i).as_str()).unwrap();
+
+            let leaves = _leaves(&tree);
+            assert_eq!(_chain(&tree.pager, leaves[0]), leaves);
+        }
+
+        assert!(matches!(tree.delete("00"), Err(Error::KeyNotFound(_))));
+        assert!(tree.lookup("02").unwrap().is_none());
+        assert!(tree.lookup("03").unwrap().is_some());
+        assert_eq!(tree.iter().count(), 25);
+        assert_eq!(_values(tree.range("10".."16").unwrap()), ["11", "13", "15"]);
+
+        drop(tree);
+        assert_eq!(BPlusTree::load(path).unwrap().iter().count(), 25);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn delete_reclaim() {
+        let path = "bplus_test_delete_reclaim";
+        let mut tree = BPlusTree::create(2, path).unwrap();
+        let empty = tree.pager.used_pages().unwrap();
+
+        for i in 0..200 {
+            tree.insert(_create_key(&format!("{:03}", i))).unwrap();
+        }
+
+        let full = tree.pager.used_pages().unwrap();
+
+        // Empties leaves at both ends and in the middle before the rest.
+        for i in (0..40).chain(160..200).chain(80..120) {
+            tree.delete(format!("{:03}", i).as_str()).unwrap();
+
+            let leaves = _leaves(&tree);
+            assert_eq!(_chain(&tree.pager, leaves[0]), leaves);
+        }
+
+        assert!(tree.pager.used_pages().unwrap() < full * 3 / 4);
+        assert_eq!(tree.iter().count(), 80);
+        assert_eq!(_values(tree.range("118".."122").unwrap()), ["120", "121"]);
+
+        for i in (40..80).chain(120..160) {
+            tree.delete(format!("{:03}", i).as_str()).unwrap();
+
+            let leaves = _leaves(&tree);
+            assert_eq!(_chain(&tree.pager, leaves[0]), leaves);
+        }
+
+        assert!(matches!(tree.root.content, Content::Leaf(_)));
+        assert_eq!(tree.pager.used_pages().unwrap(), empty);
+        assert!(tree.iter().next().is_none());
+
+        tree.insert(_create_key("A")).unwrap();
+        drop(tree);
+        assert_eq!(_values(BPlusTree::load(path).unwrap().iter()), ["A"]);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn delete_rebalance() {
+        let path = "bplus_test_delete_rebalance";
+        let order = 3;
+        let mut tree = BPlusTree::create(order, path).unwrap();
+
+        for i in 0..200 {
+            tree.insert(_create_key(&format!("{:03}", i))).unwrap();
+        }
+
+        // Thinning every leaf out would leave them all underfull without
+        // borrowing and merging.
+        for i in (0..200).filter(|i| i % 10 != 0) {
+            tree.delete(format!("{:03}", i).as_str()).unwrap();
+        }
+
+        let leaves = _leaves(&tree);
+        assert_eq!(_chain(&tree.pager, leaves[0]), leaves);
+        assert!(leaves.len() <= 20 / (order - 1));
+        assert_eq!(tree.iter().count(), 20);
+        assert_eq!(
+            _values(tree.range("085".."125").unwrap()),
+            ["090", "100", "110", "120"]
+        );
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn unique() {
+        let path = "bplus_test_unique";
+        let schema = Schema::default().unique(Duplicates::Fail);
+        let mut tree = BPlusTree::create_with_schema(2, path, schema).unwrap();
+
+        for (i, value) in ["B", "A", "C"].iter().enumerate() {
+            tree.insert(Key::create(value, (i as u64, 1))).unwrap();
+        }
+
+        match tree.insert(Key::create("A", (7, 1))) {
+            Err(Error::Duplicate(conflict)) => {
+                assert_eq!(conflict.existing, (1, 1));
+                assert_eq!(conflict.duplicate, (7, 1));
+            }
+            _ => panic!("A is already indexed"),
+        }
+
+        assert_eq!(tree.lookup("A").unwrap().unwrap().positions().count(), 1);
+        assert_eq!(tree.iter().count(), 3);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+}
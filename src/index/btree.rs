@@ -1,85 +1,545 @@
-use crate::index::key::Key;
+use crate::error::{Error, Result};
+use crate::index::bulk::Builder;
+use crate::index::cache::{Capacity, Stats};
+use crate::index::key::{Components, Key};
+use crate::index::lock::{Lock, Wait, DEFAULT_WAIT};
 use crate::index::node::Node;
-use serde::{Deserialize, Serialize};
-use std::fs;
-use std::fs::File;
-use std::io::prelude::*;
+use crate::index::paged::{self, Metadata, Paged};
+use crate::index::pager::{Pager, META_PAGE};
+use crate::index::range::Range;
+use crate::index::schema::{Duplicates, Schema};
+use crate::index::snapshot::{Pins, Snapshot};
+use crate::index::sort::{Sorter, RUN_SIZE};
+use crate::index::source::Source;
+use crate::index::value::{Kind, Value};
+use serde_json::json;
+use std::error;
+use std::fmt;
+use std::io::Write;
+use std::ops::{Bound, RangeBounds};
+use std::sync::{Arc, Mutex, PoisonError};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+    pub value: Vec<Value>,
+    pub existing: (u64, u64),
+    pub duplicate: (u64, u64),
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value: Vec<String> = self.value.iter().map(|value| value.to_string()).collect();
+
+        write!(
+            f,
+            "duplicate key {} at offset {} (already indexed at offset {})",
+            value.join(","),
+            self.duplicate.0,
+            self.existing.0
+        )
+    }
+}
+
+impl error::Error for Conflict {}
+
+pub const DEFAULT_FILL_FACTOR: f64 = 1.0;
 
-#[derive(Clone, Serialize, Deserialize)]
 pub struct BTree {
     root: Node,
     order: usize,
+    schema: Schema,
+    source: Option<Source>,
     path: String,
+    pins: Pins,
+    retired: Vec<(u64, u64)>,
+    pager: Pager,
+    lock: Arc<Mutex<Lock>>,
+}
+
+impl Paged for BTree {
+    type Saved = Vec<(u64, u64)>;
+
+    fn pager(&self) -> &Pager {
+        &self.pager
+    }
+
+    fn pager_mut(&mut self) -> &mut Pager {
+        &mut self.pager
+    }
+
+    fn lock(&self) -> &Mutex<Lock> {
+        &self.lock
+    }
+
+    fn reload(&mut self) -> Result<()> {
+        let metadata = Metadata::load(&self.pager)?;
+        self.root = Node::load(&self.pager, metadata.root)?;
+        self.source = metadata.source;
+        Ok(())
+    }
+
+    fn begin(&mut self) -> Vec<(u64, u64)> {
+        self.pager.set_copy_on_write(self.pins.oldest().is_some());
+        self.retired.clone()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.collect()
+    }
+
+    fn restore(&mut self, retired: Vec<(u64, u64)>) {
+        self.retired = retired;
+    }
 }
 
 impl BTree {
-    fn filename(&self) -> String {
-        format!("{}/btree.json", self.path)
+    fn filename(path: &str) -> String {
+        format!("{}/btree.idx", path)
     }
 
-    fn save(&self) {
-        let mut file = File::create(self.filename()).unwrap();
-        file.write_all(serde_json::to_string(self).unwrap().as_bytes())
-            .unwrap();
+    fn save(&mut self) -> Result<()> {
+        let metadata = Metadata {
+            root: self.root.page,
+            order: self.order,
+            schema: self.schema.clone(),
+            source: self.source.clone(),
+            retired: self.retired.iter().map(|(_, page)| *page).collect(),
+        };
+
+        self.pager.write(META_PAGE, &metadata.encode())
+    }
+
+    // Pages retired by a commit belong to the version it replaced, so they're
+    // tagged with that generation and freed once no snapshot of it or of an
+    // older one is alive. Pages still pinned are kept in the metadata, which
+    // lets the next session reclaim them if the tree is closed first.
+    fn collect(&mut self) -> Result<()> {
+        let generation = self.pager.generation();
+        let retired = self.pager.retired();
+
+        if retired.is_empty() && self.retired.is_empty() {
+            return Ok(());
+        }
+
+        let oldest = self.pins.oldest();
+        let (free, pinned): (Vec<_>, Vec<_>) = self
+            .retired
+            .drain(..)
+            .chain(retired.into_iter().map(|page| (generation, page)))
+            .partition(|(generation, _)| oldest.is_none_or(|oldest| *generation < oldest));
+
+        for (_, page) in free {
+            self.pager.free(page)?;
+        }
+
+        self.retired = pinned;
+        self.save()
     }
 
-    pub fn load(path: &str) -> BTree {
-        let filename = format!("{}/btree.json", path);
-        serde_json::from_slice(&fs::read(filename).unwrap()).unwrap()
+    pub fn load(path: &str) -> Result<BTree> {
+        BTree::load_with_wait(path, DEFAULT_WAIT)
     }
 
-    pub fn create(order: usize, path: &str) -> BTree {
-        fs::create_dir(path).unwrap();
+    pub fn load_with_wait(path: &str, wait: Wait) -> Result<BTree> {
+        let (lock, pager, metadata) = paged::open(&BTree::filename(path), wait)?;
 
-        let btree = BTree {
-            root: Node::empty(order, true, path),
+        let mut btree = BTree {
+            root: Node::load(&pager, metadata.root)?,
+            order: metadata.order,
+            schema: metadata.schema,
+            source: metadata.source,
             path: path.to_string(),
+            pins: Pins::default(),
+            retired: metadata.retired.into_iter().map(|page| (0, page)).collect(),
+            pager,
+            lock: Arc::new(Mutex::new(lock)),
+        };
+
+        btree.downgrade()?;
+        Ok(btree)
+    }
+
+    pub fn create(order: usize, path: &str) -> Result<BTree> {
+        BTree::create_with_schema(order, path, Schema::default())
+    }
+
+    pub fn create_with_schema(order: usize, path: &str, schema: Schema) -> Result<BTree> {
+        let (lock, mut pager) = paged::create(path, &BTree::filename(path), order, &schema)?;
+        let mut root = Node::empty(order, true, &mut pager)?;
+        root.save(&mut pager)?;
+
+        let mut btree = BTree {
+            root,
             order,
+            schema,
+            source: None,
+            path: path.to_string(),
+            pins: Pins::default(),
+            retired: Vec::new(),
+            pager,
+            lock: Arc::new(Mutex::new(lock)),
         };
 
-        btree.save();
-        btree
+        btree.save()?;
+        btree.downgrade()?;
+        Ok(btree)
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn source(&self) -> Option<&Source> {
+        self.source.as_ref()
+    }
+
+    pub fn set_source(&mut self, source: Source) -> Result<()> {
+        self.atomic(|tree| {
+            tree.source = Some(source);
+            tree.save()
+        })
+    }
+
+    pub fn check_source(&self) -> Result<()> {
+        match &self.source {
+            Some(source) => source.check(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn set_lock_wait(&mut self, wait: Wait) {
+        self.lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set_wait(wait);
+    }
+
+    pub fn set_cache_capacity(&mut self, capacity: Capacity) {
+        self.pager.set_cache_capacity(capacity);
     }
 
-    pub fn insert(&mut self, key: Key) {
+    pub fn cache_stats(&self) -> Stats {
+        self.pager.cache_stats()
+    }
+
+    pub fn write_back(&self) -> bool {
+        self.pager.write_back()
+    }
+
+    pub fn set_write_back(&mut self, enabled: bool) -> Result<()> {
+        self.pager.set_write_back(enabled)?;
+        self.downgrade()
+    }
+
+    pub fn dirty_pages(&self) -> usize {
+        self.pager.dirty_pages()
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.pager.flush()?;
+        self.downgrade()
+    }
+
+    pub fn snapshot(&mut self) -> Result<Snapshot> {
+        self.flush()?;
+
+        Snapshot::create(
+            &BTree::filename(&self.path),
+            self.root.clone(),
+            self.schema.clone(),
+            self.pins.clone(),
+            Arc::clone(&self.lock),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.leaf && self.root.keys.is_empty()
+    }
+
+    fn resolve(duplicates: Duplicates, mut key: Key, conflicts: &mut Vec<Conflict>) -> Result<Key> {
+        let mut found = key.postings.iter().map(|duplicate| Conflict {
+            value: key.value.clone(),
+            existing: key.position,
+            duplicate: *duplicate,
+        });
+
+        match (duplicates, found.next()) {
+            (Duplicates::Fail, Some(conflict)) => return Err(Error::Duplicate(conflict)),
+            (_, first) => conflicts.extend(first.into_iter().chain(found)),
+        }
+
+        if let (Duplicates::KeepLast, Some(last)) = (duplicates, key.postings.last()) {
+            key.position = *last;
+        }
+
+        key.postings.clear();
+        Ok(key)
+    }
+
+    pub fn bulk_load<I: IntoIterator<Item = Result<Key>>>(
+        &mut self,
+        keys: I,
+        fill_factor: f64,
+    ) -> Result<Vec<Conflict>> {
+        if !self.is_empty() {
+            return Err(Error::NotEmpty(self.path.clone()));
+        }
+
+        self.upgrade()?;
+        let result = self.build(keys, fill_factor, |_| Ok(()));
+        self.downgrade()?;
+        result
+    }
+
+    // Replaces every key in the tree. The source is asked for once the keys
+    // are consumed and is committed together with the new root, so no reader
+    // sees one without the other.
+    pub fn rebuild<I: IntoIterator<Item = Result<Key>>>(
+        &mut self,
+        keys: I,
+        fill_factor: f64,
+        source: impl FnOnce() -> Result<Source>,
+    ) -> Result<Vec<Conflict>> {
+        self.upgrade()?;
+        let result = self.build(keys, fill_factor, |tree| {
+            tree.source = Some(source()?);
+            Ok(())
+        });
+        self.downgrade()?;
+        result
+    }
+
+    // Nodes are written straight to the file rather than through a transaction,
+    // which would hold the whole index in memory until the commit. A failed
+    // build frees what it wrote, and only the swap to the new root is atomic.
+    fn build<I: IntoIterator<Item = Result<Key>>>(
+        &mut self,
+        keys: I,
+        fill_factor: f64,
+        update: impl FnOnce(&mut BTree) -> Result<()>,
+    ) -> Result<Vec<Conflict>> {
+        let mut builder = Builder::create(self.order, fill_factor, &mut self.pager)?;
+
+        let result = self.fill(keys, &mut builder).and_then(|conflicts| {
+            let root = builder.finish(&mut self.pager)?;
+
+            self.transaction(|tree| {
+                std::mem::replace(&mut tree.root, root).remove_all(&mut tree.pager)?;
+                update(tree)?;
+                tree.save()
+            })?;
+
+            Ok(conflicts)
+        });
+
+        if result.is_err() {
+            builder.abandon(&mut self.pager)?;
+        }
+
+        result
+    }
+
+    fn fill<I: IntoIterator<Item = Result<Key>>>(
+        &mut self,
+        keys: I,
+        builder: &mut Builder,
+    ) -> Result<Vec<Conflict>> {
+        let mut sorter = Sorter::create(&self.path, RUN_SIZE);
+
+        for key in keys {
+            sorter.push(key?)?;
+        }
+
+        let mut conflicts = Vec::new();
+
+        for key in sorter.finish()? {
+            let key = match self.schema.unique {
+                Some(duplicates) => BTree::resolve(duplicates, key?, &mut conflicts)?,
+                None => key?,
+            };
+
+            builder.push(key, &mut self.pager)?;
+        }
+
+        Ok(conflicts)
+    }
+
+    pub fn insert(&mut self, key: Key) -> Result<()> {
+        if self.schema.unique.is_some() {
+            if let Some(existing) = BTree::search_tree(&self.pager, &self.root, &key.value)? {
+                return Err(Error::Duplicate(Conflict {
+                    value: key.value,
+                    existing: existing.position,
+                    duplicate: key.position,
+                }));
+            }
+        }
+
+        self.atomic(|tree| tree.put(key))
+    }
+
+    pub fn replace(&mut self, key: Key) -> Result<Option<Key>> {
+        self.atomic(|tree| {
+            let existing = tree.remove(&key.value)?;
+            tree.put(key)?;
+            Ok(existing)
+        })
+    }
+
+    fn put(&mut self, key: Key) -> Result<()> {
         if self.root.is_full(self.order) {
-            let mut new_root = Node::empty(self.order, false, &self.path);
-            new_root.children.push(self.root.clone().filename);
-            new_root.split(0, self.order, &self.path);
+            let mut new_root = Node::empty(self.order, false, &mut self.pager)?;
+            new_root.children.push(self.root.page);
+            new_root.split(0, self.order, &mut self.pager)?;
             self.root = new_root;
         }
 
-        self.root.insert(key, self.order, &self.path);
-        self.save();
+        self.root.insert(key, self.order, &mut self.pager)?;
+        self.save()
     }
 
-    fn search_tree(node: &Node, value: String) -> Option<Key> {
+    pub(crate) fn search_tree(pager: &Pager, node: &Node, value: &[Value]) -> Result<Option<Key>> {
         for (i, key) in node.keys.iter().enumerate() {
             if key.value == value {
-                return Some(key.clone());
-            } else if key.value > value {
+                return Ok(Some(key.clone()));
+            } else if key.value.as_slice() > value {
                 if node.leaf {
-                    return None;
+                    return Ok(None);
                 } else {
-                    return BTree::search_tree(&Node::load(&node.children[i]), value);
+                    return BTree::search_tree(pager, &Node::load(pager, node.children[i])?, value);
                 }
             }
         }
 
         if node.leaf {
-            None
+            Ok(None)
         } else {
-            BTree::search_tree(&Node::load(&node.children[node.keys.len()]), value)
+            BTree::search_tree(
+                pager,
+                &Node::load(pager, node.children[node.keys.len()])?,
+                value,
+            )
         }
     }
 
-    pub fn search(&self, value: &str) -> Option<Key> {
-        BTree::search_tree(&self.root, value.to_string())
+    pub fn search(&self, value: &str) -> Result<Option<Key>> {
+        self.lookup(value)
+    }
+
+    pub fn search_all(&self, value: &str) -> Result<Vec<(u64, u64)>> {
+        Ok(self
+            .lookup(value)?
+            .map(|key| key.positions().collect())
+            .unwrap_or_default())
+    }
+
+    pub fn lookup<C: Components>(&self, value: C) -> Result<Option<Key>> {
+        match self.schema.parse(&value.components()) {
+            Ok(value) => BTree::search_tree(&self.pager, &self.root, &value),
+            Err(_) => Ok(None),
+        }
+    }
+
+    pub fn lookup_prefix<C: Components>(&self, prefix: C) -> Result<Range<'_>> {
+        let prefix = self.schema.parse(&prefix.components())?;
+        Ok(Range::create_prefix(&self.pager, &self.root, prefix))
+    }
+
+    // Matches the text of the leading column. Strings sort by their text, so
+    // the matches are contiguous and the scan starts at the prefix and stops
+    // at the first key past it; other kinds are scanned in full.
+    pub fn prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = Result<Key>> + 'a {
+        let ordered = self
+            .schema
+            .kinds
+            .first()
+            .is_none_or(|kind| *kind == Kind::Str);
+        let start = match ordered {
+            true => Bound::Included(vec![Value::from(prefix)]),
+            false => Bound::Unbounded,
+        };
+
+        let matches = move |key: &Result<Key>| match key {
+            Ok(key) => match &key.value[0] {
+                Value::Str(text) => text.starts_with(prefix),
+                value => value.to_string().starts_with(prefix),
+            },
+            Err(_) => true,
+        };
+
+        Range::bounded(&self.pager, &self.root, start, Bound::Unbounded, false)
+            .take_while(move |key| !ordered || matches(key))
+            .filter(matches)
+    }
+
+    pub fn range<'a, R: RangeBounds<&'a str>>(&self, bounds: R) -> Result<Range<'_>> {
+        Ok(Range::bounded(
+            &self.pager,
+            &self.root,
+            self.schema.parse_bound(bounds.start_bound())?,
+            self.schema.parse_bound(bounds.end_bound())?,
+            false,
+        ))
+    }
+
+    pub fn iter(&self) -> Range<'_> {
+        Range::create(&self.pager, &self.root, ..)
+    }
+
+    pub fn iter_rev(&self) -> Range<'_> {
+        Range::create_rev(&self.pager, &self.root, ..)
+    }
+
+    pub fn export_json<W: Write>(&self, writer: W) -> Result<()> {
+        let tree = json!({
+            "order": self.order,
+            "root": self.root.to_json(&self.pager)?,
+        });
+
+        serde_json::to_writer_pretty(writer, &tree).map_err(std::io::Error::from)?;
+        Ok(())
+    }
+
+    pub fn delete<C: Components>(&mut self, value: C) -> Result<Key> {
+        let components = value.components();
+        let missing = || Error::KeyNotFound(components.join(","));
+
+        match self.schema.parse(&components) {
+            Ok(value) => self.atomic(|tree| tree.remove(&value))?.ok_or_else(missing),
+            Err(_) => Err(missing()),
+        }
+    }
+
+    fn remove(&mut self, value: &[Value]) -> Result<Option<Key>> {
+        let key = self.root.delete(value, self.order, &mut self.pager)?;
+
+        if !self.root.leaf && self.root.keys.is_empty() {
+            let new_root = Node::load(&self.pager, self.root.children[0])?;
+            std::mem::replace(&mut self.root, new_root).remove(&mut self.pager)?;
+        }
+
+        self.save()?;
+        Ok(key)
     }
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
+    use crate::index::encoding::FORMAT_VERSION;
+    use crate::index::lock::Mode;
+    use crate::index::schema::Column;
+    use crate::index::value::Kind;
+    use std::fs;
+    use std::path::Path;
     use uuid::Uuid;
     const _PLACEHOLDER: (u64, u64) = (0, 0);
 
@@ -87,50 +547,87 @@ mod tests {
         Key::create(value, _PLACEHOLDER)
     }
 
-    fn _valid_tree(node: &Node, limit: Option<&String>) -> bool {
-        for i in 1..node.keys.len() {
-            if node.keys[i - 1].value >= node.keys[i].value {
-                return false;
+    // Walks the whole tree checking keys are strictly ordered within each node
+    // and between the parent's separators, that nodes other than the root hold
+    // between t-1 and 2t-1 keys and that every leaf sits at the same depth.
+    fn _valid_tree(pager: &Pager, node: &Node, order: usize) -> bool {
+        _valid_subtree(pager, node, order, true, None, None).is_some()
+    }
+
+    fn _valid_subtree(
+        pager: &Pager,
+        node: &Node,
+        order: usize,
+        root: bool,
+        lower: Option<&Vec<Value>>,
+        upper: Option<&Vec<Value>>,
+    ) -> Option<usize> {
+        if node.keys.len() > 2 * order - 1 || (!root && node.keys.len() < order - 1) {
+            return None;
+        }
+
+        for i in 0..node.keys.len() {
+            if i > 0 && node.keys[i - 1].value >= node.keys[i].value {
+                return None;
             }
 
-            match limit {
-                None => {}
-                Some(value) => {
-                    if &node.keys[i].value > value {
-                        return false;
-                    }
-                }
+            if lower.is_some_and(|value| &node.keys[i].value <= value)
+                || upper.is_some_and(|value| &node.keys[i].value >= value)
+            {
+                return None;
             }
         }
 
         if node.leaf {
-            true
-        } else {
-            match node
-                .children
-                .iter()
-                .enumerate()
-                .map(|(i, node_filename)| {
-                    let node = Node::load(node_filename);
-                    if i < node.keys.len() {
-                        _valid_tree(&Node::load(&node.children[i]), Some(&node.keys[i].value))
-                    } else {
-                        _valid_tree(&Node::load(&node.children[i]), None)
-                    }
-                })
-                .reduce(|acc, e| acc & e)
-            {
-                None => false,
-                Some(result) => result,
-            }
+            return match node.children.is_empty() {
+                true => Some(1),
+                false => None,
+            };
+        }
+
+        if node.children.len() != node.keys.len() + 1 {
+            return None;
         }
+
+        let depths: Option<Vec<usize>> = node
+            .children
+            .iter()
+            .enumerate()
+            .map(|(i, child_page)| {
+                let child = Node::load(pager, *child_page).unwrap();
+                let lower = match i {
+                    0 => lower,
+                    _ => Some(&node.keys[i - 1].value),
+                };
+                let upper = match node.keys.get(i) {
+                    None => upper,
+                    Some(key) => Some(&key.value),
+                };
+
+                _valid_subtree(pager, &child, order, false, lower, upper)
+            })
+            .collect();
+
+        match depths?.as_slice() {
+            [first, rest @ ..] if rest.iter().all(|depth| depth == first) => Some(first + 1),
+            _ => None,
+        }
+    }
+
+    fn _count_nodes(pager: &Pager, node: &Node) -> usize {
+        1 + node
+            .children
+            .iter()
+            .map(|child_page| _count_nodes(pager, &Node::load(pager, *child_page).unwrap()))
+            .sum::<usize>()
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn create() {
         let order = 3;
         let path = "btree_test_create";
-        let tree = BTree::create(order, path);
+        let tree = BTree::create(order, path).unwrap();
 
         assert_eq!(tree.order, order);
         assert_eq!(tree.root.leaf, true);
@@ -138,11 +635,60 @@ mod tests {
         fs::remove_dir_all(path).unwrap();
     }
 
+    #[test]
+    fn create_invalid() {
+        let path = "btree_test_create_invalid";
+
+        assert!(matches!(
+            BTree::create(1, path),
+            Err(Error::InvalidOrder(1))
+        ));
+        assert!(matches!(
+            BTree::create_with_schema(3, path, Schema::default().typed(Vec::new())),
+            Err(Error::InvalidSchema(_))
+        ));
+        assert!(!Path::new(path).exists());
+    }
+
+    #[test]
+    fn create_with_schema() {
+        let path = "btree_test_create_with_schema";
+        let tree = BTree::create_with_schema(3, path, Schema::name("city")).unwrap();
+
+        assert_eq!(tree.schema(), &Schema::name("city"));
+        assert_eq!(BTree::load(path).unwrap().schema(), &Schema::name("city"));
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn source() {
+        let path = "btree_test_source";
+        let source = "btree_test_source.csv";
+        fs::write(source, "1,Ana\n").unwrap();
+
+        let mut tree = BTree::create(3, path).unwrap();
+        assert!(tree.source().is_none());
+        assert!(tree.check_source().is_ok());
+
+        tree.set_source(Source::read(source).unwrap()).unwrap();
+
+        let tree = BTree::load(path).unwrap();
+        assert_eq!(tree.source(), Some(&Source::read(source).unwrap()));
+        assert!(tree.check_source().is_ok());
+
+        fs::write(source, "1,Ana\n2,Bia\n").unwrap();
+        assert!(matches!(tree.check_source(), Err(Error::Stale(_))));
+
+        fs::remove_file(source).unwrap();
+        fs::remove_dir_all(path).unwrap();
+    }
+
     #[test]
     fn insert() {
         let path = "btree_test_insert";
         let order = 3;
-        let mut tree = BTree::create(order, path);
+        let mut tree = BTree::create(order, path).unwrap();
 
         tree.root.keys = vec![
             _create_key("G"),
@@ -152,34 +698,34 @@ mod tests {
         ];
 
         tree.root.leaf = false;
-        tree.root.save();
+        tree.root.save(&mut tree.pager).unwrap();
 
         tree.root.children = vec![
             {
-                let mut child = Node::empty(order, true, path);
+                let mut child = Node::empty(order, true, &mut tree.pager).unwrap();
                 child.keys = vec![
                     _create_key("A"),
                     _create_key("C"),
                     _create_key("D"),
                     _create_key("E"),
                 ];
-                child.save();
-                child.filename
+                child.save(&mut tree.pager).unwrap();
+                child.page
             },
             {
-                let mut child = Node::empty(order, true, path);
+                let mut child = Node::empty(order, true, &mut tree.pager).unwrap();
                 child.keys = vec![_create_key("J"), _create_key("K")];
-                child.save();
-                child.filename
+                child.save(&mut tree.pager).unwrap();
+                child.page
             },
             {
-                let mut child = Node::empty(order, true, path);
+                let mut child = Node::empty(order, true, &mut tree.pager).unwrap();
                 child.keys = vec![_create_key("N"), _create_key("O")];
-                child.save();
-                child.filename
+                child.save(&mut tree.pager).unwrap();
+                child.page
             },
             {
-                let mut child = Node::empty(order, true, path);
+                let mut child = Node::empty(order, true, &mut tree.pager).unwrap();
                 child.keys = vec![
                     _create_key("R"),
                     _create_key("S"),
@@ -187,64 +733,71 @@ mod tests {
                     _create_key("U"),
                     _create_key("V"),
                 ];
-                child.save();
-                child.filename
+                child.save(&mut tree.pager).unwrap();
+                child.page
             },
             {
-                let mut child = Node::empty(order, true, path);
+                let mut child = Node::empty(order, true, &mut tree.pager).unwrap();
                 child.keys = vec![_create_key("Y"), _create_key("Z")];
-                child.save();
-                child.filename
+                child.save(&mut tree.pager).unwrap();
+                child.page
             },
         ];
 
-        tree.insert(_create_key("B"));
-        tree.insert(_create_key("Q"));
-        tree.insert(_create_key("L"));
-        tree.insert(_create_key("F"));
+        tree.insert(_create_key("B")).unwrap();
+        tree.insert(_create_key("Q")).unwrap();
+        tree.insert(_create_key("L")).unwrap();
+        tree.insert(_create_key("F")).unwrap();
 
-        assert!(_valid_tree(&tree.root, None));
+        assert!(_valid_tree(&tree.pager, &tree.root, tree.order));
 
         fs::remove_dir_all(path).unwrap();
     }
 
     #[test]
+    #[allow(
+        clippy::redundant_pattern_matching,
+        clippy::manual_unwrap_or,
+        clippy::manual_unwrap_or_default
+    )]
     fn search() {
         let order = 3;
         let path = "btree_test_search";
-        let mut tree = BTree::create(order, path);
+        let mut tree = BTree::create(order, path).unwrap();
 
         let uuids: Vec<String> = (0..100).map(|_| Uuid::new_v4().to_string()).collect();
 
         for uuid in &uuids {
-            tree.insert(_create_key(uuid));
+            tree.insert(_create_key(uuid)).unwrap();
 
-            assert!(match tree.search(uuid) {
+            assert!(match tree.search(uuid).unwrap() {
                 None => {
                     false
                 }
                 Some(key) => {
-                    key.value == *uuid
+                    key.value == [uuid.as_str()]
                 }
             });
         }
 
         for uuid in uuids {
-            assert!(match tree.search(&uuid) {
+            assert!(match tree.search(&uuid).unwrap() {
                 None => {
                     false
                 }
                 Some(key) => {
-                    key.value == uuid
+                    key.value == [uuid.as_str()]
                 }
             });
         }
 
         let none_found: Option<bool> = (0..100)
-            .map(|_| match tree.search(&Uuid::new_v4().to_string()) {
-                None => true,
-                Some(_) => false,
-            })
+            .map(
+                |_| match tree.search(&Uuid::new_v4().to_string()).unwrap() {
+                    None => true,
+                    Some(_) => false,
+                },
+            )
             .reduce(|acc, e| acc & e);
 
         assert!(match none_found {
@@ -258,33 +811,40 @@ mod tests {
     }
 
     #[test]
+    #[allow(
+        clippy::redundant_pattern_matching,
+        clippy::manual_unwrap_or,
+        clippy::manual_unwrap_or_default
+    )]
     fn load() {
         let path = "btree_test_load";
         let order = 3;
-        let mut tree = BTree::create(order, path);
+        let mut tree = BTree::create(order, path).unwrap();
 
         let uuids: Vec<String> = (0..100).map(|_| Uuid::new_v4().to_string()).collect();
 
         for uuid in &uuids {
-            tree.insert(_create_key(uuid));
+            tree.insert(_create_key(uuid)).unwrap();
 
-            assert!(match tree.search(uuid) {
+            assert!(match tree.search(uuid).unwrap() {
                 None => {
                     false
                 }
                 Some(key) => {
-                    key.value == *uuid
+                    key.value == [uuid.as_str()]
                 }
             });
         }
 
-        let tree_loaded = BTree::load(path);
+        let tree_loaded = BTree::load(path).unwrap();
 
         let none_found: Option<bool> = (0..100)
-            .map(|_| match tree_loaded.search(&Uuid::new_v4().to_string()) {
-                None => true,
-                Some(_) => false,
-            })
+            .map(
+                |_| match tree_loaded.search(&Uuid::new_v4().to_string()).unwrap() {
+                    None => true,
+                    Some(_) => false,
+                },
+            )
             .reduce(|acc, e| acc & e);
 
         assert!(match none_found {
@@ -295,7 +855,745 @@ mod tests {
         });
 
         fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn delete() {
+        let path = "btree_test_delete";
+        let order = 3;
+        let mut tree = BTree::create(order, path).unwrap();
+
+        let uuids: Vec<String> = (0..200).map(|_| Uuid::new_v4().to_string()).collect();
+
+        for uuid in &uuids {
+            tree.insert(_create_key(uuid)).unwrap();
+        }
+
+        let (removed, kept) = uuids.split_at(150);
+
+        for uuid in removed {
+            assert!(match tree.delete(uuid) {
+                Err(_) => false,
+                Ok(key) => key.value == [uuid.as_str()],
+            });
+
+            assert!(tree.search(uuid).unwrap().is_none());
+            assert!(_valid_tree(&tree.pager, &tree.root, tree.order));
+        }
+
+        assert!(matches!(
+            tree.delete(&removed[0]),
+            Err(Error::KeyNotFound(_))
+        ));
+
+        for uuid in kept {
+            assert!(tree.search(uuid).unwrap().is_some());
+        }
+
+        assert_eq!(
+            tree.pager.used_pages().unwrap(),
+            _count_nodes(&tree.pager, &tree.root) as u64 + 2
+        );
+
+        for uuid in kept {
+            assert!(tree.delete(uuid).is_ok());
+        }
+
+        let tree_loaded = BTree::load(path).unwrap();
+        assert!(tree_loaded.root.leaf);
+        assert!(tree_loaded.root.keys.is_empty());
+        assert_eq!(tree_loaded.pager.used_pages().unwrap(), 3);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn lookup() {
+        let path = "btree_test_lookup";
+        let order = 3;
+        let schema = Schema::composite(vec![Column::Position(0), Column::Position(1)], false)
+            .typed(vec![Kind::Str, Kind::UInt]);
+        let mut tree = BTree::create_with_schema(order, path, schema).unwrap();
+
+        for (i, country) in ["PT", "BR", "AR"].iter().enumerate() {
+            for code in ["10", "2", "30"] {
+                let value = tree.schema().parse(&(*country, code).components()).unwrap();
+                tree.insert(Key::composite(value, (i as u64, 0))).unwrap();
+            }
+        }
+
+        let key = tree.lookup(("BR", "2")).unwrap().unwrap();
+        assert_eq!(key.value, [Value::from("BR"), Value::UInt(2)]);
+        assert_eq!(key.position, (1, 0));
+
+        assert!(tree.lookup(["BR", "20"]).unwrap().is_none());
+        assert!(tree.lookup(["BR", "two"]).unwrap().is_none());
+        assert!(tree.lookup("BR").unwrap().is_none());
+
+        let found: Vec<String> = tree
+            .lookup_prefix("BR")
+            .unwrap()
+            .map(|key| key.unwrap().value[1].to_string())
+            .collect();
+        assert_eq!(found, ["2", "10", "30"]);
+
+        assert_eq!(tree.lookup_prefix(["PT", "30"]).unwrap().count(), 1);
+        assert_eq!(tree.lookup_prefix("CL").unwrap().count(), 0);
+
+        assert!(tree.delete(("AR", "10")).is_ok());
+        assert_eq!(tree.lookup_prefix("AR").unwrap().count(), 2);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn search_all() {
+        let path = "btree_test_search_all";
+        let order = 3;
+        let mut tree = BTree::create(order, path).unwrap();
+
+        for i in 0..100 {
+            tree.insert(Key::create(&format!("{:02}", i % 10), (i, 1)))
+                .unwrap();
+        }
+
+        assert_eq!(tree.iter().count(), 10);
+        assert!(_valid_tree(&tree.pager, &tree.root, tree.order));
+        assert_eq!(
+            tree.search_all("03").unwrap(),
+            (0..10).map(|i| (i * 10 + 3, 1)).collect::<Vec<_>>()
+        );
+        assert!(tree.search_all("10").unwrap().is_empty());
+        drop(tree);
+
+        let mut tree = BTree::load(path).unwrap();
+        assert_eq!(tree.search_all("07").unwrap().len(), 10);
+
+        assert_eq!(tree.delete("07").unwrap().postings.len(), 9);
+        assert!(tree.search_all("07").unwrap().is_empty());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn unique() {
+        let path = "btree_test_unique";
+        let schema = Schema::position(0, false).unique(Duplicates::Fail);
+        let mut tree = BTree::create_with_schema(3, path, schema).unwrap();
+
+        for i in 0..50 {
+            tree.insert(Key::create(&format!("{:02}", i), (i, 1)))
+                .unwrap();
+        }
+
+        let conflict = match tree.insert(Key::create("07", (100, 2))) {
+            Err(Error::Duplicate(conflict)) => conflict,
+            _ => panic!("expected a duplicate key error"),
+        };
+        assert_eq!(conflict.existing, (7, 1));
+        assert_eq!(conflict.duplicate, (100, 2));
+        assert_eq!(
+            conflict.to_string(),
+            "duplicate key 07 at offset 100 (already indexed at offset 7)"
+        );
+        assert_eq!(tree.search_all("07").unwrap(), [(7, 1)]);
+
+        let replaced = tree.replace(Key::create("07", (100, 2))).unwrap().unwrap();
+        assert_eq!(replaced.position, (7, 1));
+        assert_eq!(tree.search_all("07").unwrap(), [(100, 2)]);
+        assert!(_valid_tree(&tree.pager, &tree.root, tree.order));
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn bulk_load_unique() {
+        let path = "btree_test_bulk_load_unique";
+
+        for (duplicates, position) in [
+            (Duplicates::KeepFirst, (3, 1)),
+            (Duplicates::KeepLast, (43, 1)),
+        ] {
+            let schema = Schema::position(0, false).unique(duplicates);
+            let mut tree = BTree::create_with_schema(3, path, schema).unwrap();
+
+            let conflicts = tree
+                .bulk_load(
+                    (0..50).map(|i| Ok(Key::create(&format!("{:02}", i % 20), (i, 1)))),
+                    DEFAULT_FILL_FACTOR,
+                )
+                .unwrap();
+
+            assert_eq!(conflicts.len(), 30);
+            assert_eq!(conflicts[0].existing, (0, 1));
+            assert_eq!(conflicts[0].duplicate, (20, 1));
+
+            assert_eq!(tree.iter().count(), 20);
+            assert_eq!(tree.search_all("03").unwrap(), [position]);
+
+            fs::remove_dir_all(path).unwrap();
+        }
+
+        let schema = Schema::position(0, false).unique(Duplicates::Fail);
+        let mut tree = BTree::create_with_schema(3, path, schema).unwrap();
+
+        match tree.bulk_load(
+            (0..50).map(|i| Ok(Key::create(&format!("{:02}", i % 20), (i, 1)))),
+            DEFAULT_FILL_FACTOR,
+        ) {
+            Err(Error::Duplicate(conflict)) => assert_eq!(conflict.duplicate, (20, 1)),
+            _ => panic!("expected a duplicate"),
+        }
+
+        assert!(tree.is_empty());
+        assert_eq!(tree.pager.used_pages().unwrap(), 3);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn typed() {
+        let path = "btree_test_typed";
+        let schema = Schema::position(0, false).typed(vec![Kind::Int]);
+        let mut tree = BTree::create_with_schema(3, path, schema).unwrap();
+
+        for (i, value) in ["100", "20", "-5", "3", "1000"].iter().enumerate() {
+            let value = tree.schema().parse(&value.components()).unwrap();
+            tree.insert(Key::composite(value, (i as u64, 0))).unwrap();
+        }
+
+        let found: Vec<String> = tree
+            .iter()
+            .map(|key| key.unwrap().value[0].to_string())
+            .collect();
+        assert_eq!(found, ["-5", "3", "20", "100", "1000"]);
+
+        let found: Vec<String> = tree
+            .range("0".."150")
+            .unwrap()
+            .map(|key| key.unwrap().value[0].to_string())
+            .collect();
+        assert_eq!(found, ["3", "20", "100"]);
+
+        assert_eq!(tree.search("020").unwrap().unwrap().position, (1, 0));
+        assert!(tree.search("twenty").unwrap().is_none());
+
+        let tree = BTree::load(path).unwrap();
+        assert_eq!(tree.schema().kinds, [Kind::Int]);
+        assert_eq!(tree.search("1000").unwrap().unwrap().position, (4, 0));
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn typed_range() {
+        let path = "btree_test_typed_range";
+        let schema = Schema::position(0, false).typed(vec![Kind::Int]);
+        let tree = BTree::create_with_schema(3, path, schema).unwrap();
+
+        match tree.range("ten"..) {
+            Err(error @ Error::Parse { .. }) => {
+                assert_eq!(error.to_string(), "can't parse \"ten\" as i64")
+            }
+            _ => panic!("expected a parse error"),
+        }
+
+        assert!(tree.lookup_prefix("ten").is_err());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn load_corrupt() {
+        let path = "btree_test_load_corrupt";
+
+        assert!(matches!(BTree::load(path), Err(Error::Io(_))));
+
+        let mut tree = BTree::create(3, path).unwrap();
+
+        for value in ["A", "B", "C", "D", "E", "F"] {
+            tree.insert(_create_key(value)).unwrap();
+        }
+
+        let child = tree.root.children[0];
+        tree.pager.write(child, &[FORMAT_VERSION, 1, 5]).unwrap();
+        drop(tree);
+
+        let tree = BTree::load(path).unwrap();
+        assert!(matches!(tree.search("A"), Err(Error::Corrupt(_))));
+        assert!(matches!(
+            tree.iter().collect::<Result<Vec<Key>>>(),
+            Err(Error::Corrupt(_))
+        ));
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn range() {
+        let order = 3;
+        let path = "btree_test_range";
+        let mut tree = BTree::create(order, path).unwrap();
+
+        let mut uuids: Vec<String> = (0..200).map(|_| Uuid::new_v4().to_string()).collect();
+
+        for uuid in &uuids {
+            tree.insert(_create_key(uuid)).unwrap();
+        }
+
+        uuids.sort();
+
+        let (start, end) = (uuids[20].as_str(), uuids[120].as_str());
+
+        let found: Vec<String> = tree
+            .range(start..end)
+            .unwrap()
+            .map(|key| key.unwrap().value[0].to_string())
+            .collect();
+        assert_eq!(found, uuids[20..120]);
+
+        let found: Vec<String> = tree
+            .range(start..=end)
+            .unwrap()
+            .map(|key| key.unwrap().value[0].to_string())
+            .collect();
+        assert_eq!(found, uuids[20..=120]);
+
+        let found: Vec<String> = tree
+            .range(..)
+            .unwrap()
+            .map(|key| key.unwrap().value[0].to_string())
+            .collect();
+        assert_eq!(found, uuids);
+
+        assert_eq!(tree.range(end..start).unwrap().count(), 0);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn iter() {
+        let order = 3;
+        let path = "btree_test_iter";
+        let mut tree = BTree::create(order, path).unwrap();
+
+        let mut uuids: Vec<String> = (0..200).map(|_| Uuid::new_v4().to_string()).collect();
+
+        for uuid in &uuids {
+            tree.insert(_create_key(uuid)).unwrap();
+        }
+
+        uuids.sort();
+
+        let found: Vec<String> = tree
+            .iter()
+            .map(|key| key.unwrap().value[0].to_string())
+            .collect();
+        assert_eq!(found, uuids);
+
+        uuids.reverse();
+
+        let found: Vec<String> = tree
+            .iter_rev()
+            .map(|key| key.unwrap().value[0].to_string())
+            .collect();
+        assert_eq!(found, uuids);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn prefix() {
+        let path = "btree_test_prefix";
+        let mut tree = BTree::create(3, path).unwrap();
+
+        let mut uuids: Vec<String> = (0..200).map(|_| Uuid::new_v4().to_string()).collect();
+
+        for uuid in &uuids {
+            tree.insert(_create_key(uuid)).unwrap();
+        }
+
+        uuids.sort();
+
+        for prefix in [&uuids[20][..2], &uuids[199][..1], ""] {
+            let found: Vec<String> = tree
+                .prefix(prefix)
+                .map(|key| key.unwrap().value[0].to_string())
+                .collect();
+            let expected: Vec<String> = uuids
+                .iter()
+                .filter(|uuid| uuid.starts_with(prefix))
+                .cloned()
+                .collect();
+            assert_eq!(found, expected);
+        }
+
+        assert_eq!(tree.prefix("xyz").count(), 0);
+        drop(tree);
+
+        let path_typed = "btree_test_prefix_typed";
+        let schema = Schema::position(0, false).typed(vec![Kind::Int]);
+        let mut tree = BTree::create_with_schema(3, path_typed, schema).unwrap();
+
+        for (i, value) in ["1", "2", "10", "-1", "15", "100", "21"].iter().enumerate() {
+            let value = tree.schema().parse(&value.components()).unwrap();
+            tree.insert(Key::composite(value, (i as u64, 0))).unwrap();
+        }
+
+        let found: Vec<String> = tree
+            .prefix("1")
+            .map(|key| key.unwrap().value[0].to_string())
+            .collect();
+        assert_eq!(found, ["1", "10", "15", "100"]);
+
+        fs::remove_dir_all(path).unwrap();
+        fs::remove_dir_all(path_typed).unwrap();
+    }
+
+    #[test]
+    fn export_json() {
+        let order = 2;
+        let path = "btree_test_export_json";
+        let mut tree = BTree::create(order, path).unwrap();
+
+        for value in ["A", "B", "C", "D"] {
+            tree.insert(_create_key(value)).unwrap();
+        }
+
+        let mut buf = Vec::new();
+        tree.export_json(&mut buf).unwrap();
+
+        let exported: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+
+        assert_eq!(exported["order"], 2);
+        assert_eq!(exported["root"]["leaf"], false);
+        assert_eq!(exported["root"]["keys"][0]["value"][0], "B");
+        assert_eq!(exported["root"]["children"][1]["keys"][1]["value"][0], "D");
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn cache_stats() {
+        let order = 3;
+        let path = "btree_test_cache_stats";
+        let mut tree = BTree::create(order, path).unwrap();
+
+        let uuids: Vec<String> = (0..100).map(|_| Uuid::new_v4().to_string()).collect();
+
+        for uuid in &uuids {
+            tree.insert(_create_key(uuid)).unwrap();
+        }
+
+        let tree = BTree::load(path).unwrap();
+        let misses = tree.cache_stats().misses;
+
+        for uuid in &uuids {
+            assert!(tree.search(uuid).unwrap().is_some());
+        }
+
+        let stats = tree.cache_stats();
+        assert!(stats.misses - misses <= stats.entries as u64);
+        assert!(stats.hits > 0);
+
+        let mut tree = BTree::load(path).unwrap();
+        tree.set_cache_capacity(Capacity::Nodes(0));
+
+        for uuid in &uuids {
+            assert!(tree.search(uuid).unwrap().is_some());
+        }
+
+        assert_eq!(tree.cache_stats().hits, 0);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn flush() {
+        let order = 3;
+        let path = "btree_test_flush";
+        let mut tree = BTree::create(order, path).unwrap();
+        tree.set_write_back(true).unwrap();
+
+        let uuids: Vec<String> = (0..100).map(|_| Uuid::new_v4().to_string()).collect();
+
+        for uuid in &uuids {
+            tree.insert(_create_key(uuid)).unwrap();
+        }
+
+        assert!(tree.dirty_pages() > 0);
+        assert!(matches!(
+            BTree::load_with_wait(path, Wait::NoWait),
+            Err(Error::Locked { .. })
+        ));
+
+        tree.flush().unwrap();
+
+        assert_eq!(tree.dirty_pages(), 0);
+        assert!(BTree::load(path)
+            .unwrap()
+            .search(&uuids[0])
+            .unwrap()
+            .is_some());
+
+        tree.delete(&uuids[0]).unwrap();
+        drop(tree);
+
+        let tree = BTree::load(path).unwrap();
+        assert!(tree.search(&uuids[0]).unwrap().is_none());
+        assert!(uuids[1..]
+            .iter()
+            .all(|uuid| tree.search(uuid).unwrap().is_some()));
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn bulk_load() {
+        let order = 3;
+        let path = "btree_test_bulk_load";
+        let mut tree = BTree::create(order, path).unwrap();
+
+        let mut uuids: Vec<String> = (0..500).map(|_| Uuid::new_v4().to_string()).collect();
+
+        tree.bulk_load(uuids.iter().map(|uuid| Ok(_create_key(uuid))), 0.75)
+            .unwrap();
+
+        assert!(_valid_tree(&tree.pager, &tree.root, tree.order));
+        assert_eq!(
+            tree.pager.used_pages().unwrap(),
+            _count_nodes(&tree.pager, &tree.root) as u64 + 2
+        );
+
+        assert!(matches!(
+            tree.bulk_load([Ok(_create_key("A"))], 1.0),
+            Err(Error::NotEmpty(_))
+        ));
+        drop(tree);
+
+        let tree = BTree::load(path).unwrap();
+
+        uuids.sort();
+        let found: Vec<String> = tree
+            .iter()
+            .map(|key| key.unwrap().value[0].to_string())
+            .collect();
+        assert_eq!(found, uuids);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn rebuild() {
+        let order = 2;
+        let path = "btree_test_rebuild";
+        let source = "btree_test_rebuild.csv";
+        fs::write(source, "1,Ana\n").unwrap();
+
+        let mut tree = BTree::create(order, path).unwrap();
+        let values: Vec<String> = (0..100).map(|i| format!("{:03}", i)).collect();
+
+        tree.bulk_load(values.iter().map(|value| Ok(_create_key(value))), 1.0)
+            .unwrap();
+        let used = tree.pager.used_pages().unwrap();
+        let snapshot = tree.snapshot().unwrap();
+
+        let failed = tree.rebuild([Ok(_create_key("A")), Err(Error::Poisoned)], 1.0, || {
+            Source::read(source)
+        });
+        assert!(matches!(failed, Err(Error::Poisoned)));
+        assert_eq!(tree.iter().count(), 100);
+        assert!(tree.source().is_none());
+
+        tree.rebuild(
+            values[50..].iter().map(|value| Ok(_create_key(value))),
+            1.0,
+            || Source::read(source),
+        )
+        .unwrap();
+
+        assert!(_valid_tree(&tree.pager, &tree.root, tree.order));
+        assert_eq!(tree.iter().count(), 50);
+        assert!(tree.lookup("010").unwrap().is_none());
+        assert_eq!(snapshot.iter().count(), 100);
+
+        drop(snapshot);
+        tree.insert(_create_key("A")).unwrap();
+        assert!(tree.pager.used_pages().unwrap() < used);
+        drop(tree);
+
+        let tree = BTree::load(path).unwrap();
+        assert_eq!(tree.iter().count(), 51);
+        assert_eq!(tree.source(), Some(&Source::read(source).unwrap()));
+
+        fs::remove_file(source).unwrap();
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn crash() {
+        let order = 2;
+        let path = "btree_test_crash";
+        let mut tree = BTree::create(order, path).unwrap();
+
+        for value in ["A", "B", "C"] {
+            tree.insert(_create_key(value)).unwrap();
+        }
+
+        tree.pager.begin();
+
+        for value in ["D", "E", "F", "G"] {
+            tree.put(_create_key(value)).unwrap();
+        }
+
+        assert!(!tree.root.leaf);
+        drop(tree);
+
+        let tree = BTree::load(path).unwrap();
+        assert!(tree.root.leaf);
+        assert!(_valid_tree(&tree.pager, &tree.root, tree.order));
+        assert_eq!(tree.iter().count(), 3);
+        assert_eq!(tree.pager.used_pages().unwrap(), 3);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn lock() {
+        let path = "btree_test_lock";
+        let mut tree = BTree::create(3, path).unwrap();
+        tree.set_lock_wait(Wait::NoWait);
+        tree.insert(_create_key("A")).unwrap();
+
+        let reader = BTree::load_with_wait(path, Wait::NoWait).unwrap();
+
+        assert!(matches!(
+            tree.insert(_create_key("B")),
+            Err(Error::Locked { .. })
+        ));
+        assert!(reader.search("B").unwrap().is_none());
+        drop(reader);
+
+        tree.insert(_create_key("B")).unwrap();
+        tree.set_write_back(true).unwrap();
+        tree.insert(_create_key("C")).unwrap();
+
+        assert!(matches!(
+            BTree::load_with_wait(path, Wait::NoWait),
+            Err(Error::Locked { pid: Some(_), .. })
+        ));
+
+        tree.flush().unwrap();
+        assert_eq!(BTree::load(path).unwrap().iter().count(), 3);
+
+        drop(tree);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn batch() {
+        let path = "btree_test_batch";
+        let mut tree = BTree::create(3, path).unwrap();
+        tree.set_lock_wait(Wait::NoWait);
 
+        tree.batch(|tree| {
+            for value in ["A", "B", "C"] {
+                tree.insert(_create_key(value))?;
+                assert_eq!(tree.dirty_pages(), 0);
+                assert!(matches!(
+                    BTree::load_with_wait(path, Wait::NoWait),
+                    Err(Error::Locked { pid: Some(_), .. })
+                ));
+            }
+
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(tree.mode(), Mode::Shared);
+        assert!(!tree.guard().pinned());
+        assert_eq!(BTree::load(path).unwrap().iter().count(), 3);
+
+        drop(tree);
+        fs::remove_dir_all(path).unwrap();
     }
 
+    #[test]
+    fn snapshot() {
+        let order = 2;
+        let path = "btree_test_snapshot";
+        let mut tree = BTree::create(order, path).unwrap();
+        let values: Vec<String> = (0..50).map(|i| format!("{:02}", i)).collect();
+
+        for value in &values[..25] {
+            tree.insert(_create_key(value)).unwrap();
+        }
+
+        let snapshot = tree.snapshot().unwrap();
+
+        for value in &values[25..] {
+            tree.insert(_create_key(value)).unwrap();
+        }
+
+        for value in &values[..10] {
+            tree.delete(value.as_str()).unwrap();
+        }
+
+        assert!(_valid_tree(&tree.pager, &tree.root, tree.order));
+        assert_eq!(tree.iter().count(), 40);
+        assert!(tree.lookup("03").unwrap().is_none());
+
+        assert_eq!(snapshot.iter().count(), 25);
+        assert!(snapshot.lookup("03").unwrap().is_some());
+        assert!(snapshot.lookup("30").unwrap().is_none());
+        assert_eq!(snapshot.range("20"..).unwrap().count(), 5);
+
+        let pinned = tree.pager.used_pages().unwrap();
+        assert!(!tree.retired.is_empty());
+
+        drop(snapshot);
+        tree.insert(_create_key("50")).unwrap();
+
+        assert!(tree.retired.is_empty());
+        assert!(tree.pager.used_pages().unwrap() < pinned);
+        assert!(_valid_tree(&tree.pager, &tree.root, tree.order));
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn snapshot_closed() {
+        let order = 2;
+        let path = "btree_test_snapshot_closed";
+        let mut tree = BTree::create(order, path).unwrap();
+
+        for value in ["A", "B", "C", "D"] {
+            tree.insert(_create_key(value)).unwrap();
+        }
+
+        let snapshot = tree.snapshot().unwrap();
+
+        for value in ["E", "F", "G", "H"] {
+            tree.insert(_create_key(value)).unwrap();
+        }
+
+        let retired = tree.retired.len();
+        assert!(retired > 0);
+        drop(tree);
+
+        assert_eq!(snapshot.iter().count(), 4);
+        drop(snapshot);
+
+        let mut tree = BTree::load(path).unwrap();
+        assert_eq!(tree.retired.len(), retired);
+
+        let used = tree.pager.used_pages().unwrap();
+        tree.delete("H").unwrap();
+
+        assert!(tree.retired.is_empty());
+        assert!(tree.pager.used_pages().unwrap() <= used - retired as u64);
+        assert_eq!(tree.iter().count(), 7);
+
+        fs::remove_dir_all(path).unwrap();
+    }
 }
@@ -0,0 +1,297 @@
+use crate::error::Result;
+use crate::index::key::Key;
+use crate::index::node::Node;
+use crate::index::pager::Pager;
+
+// Nodes are filled up to the fill factor as keys arrive, so the builder never
+// needs to know how many there will be. Each level keeps its last finished
+// node unsaved: only the final node of a level can come up short, and it's
+// evened out with that neighbour once the input ends.
+struct Level {
+    node: Node,
+    previous: Option<(Node, Key)>,
+}
+
+pub struct Builder {
+    order: usize,
+    per_node: usize,
+    levels: Vec<Level>,
+    pages: Vec<u64>,
+}
+
+impl Builder {
+    pub fn create(order: usize, fill_factor: f64, pager: &mut Pager) -> Result<Builder> {
+        let max_keys = 2 * order - 1;
+        let per_node =
+            ((fill_factor * max_keys as f64).round() as usize).clamp(order - 1, max_keys);
+
+        let mut builder = Builder {
+            order,
+            per_node,
+            levels: Vec::new(),
+            pages: Vec::new(),
+        };
+
+        let node = builder.empty(true, pager)?;
+        builder.levels.push(Level {
+            node,
+            previous: None,
+        });
+
+        Ok(builder)
+    }
+
+    fn empty(&mut self, leaf: bool, pager: &mut Pager) -> Result<Node> {
+        let node = Node::empty(self.order, leaf, pager)?;
+        self.pages.push(node.page);
+        Ok(node)
+    }
+
+    // A key that doesn't fit in the open node becomes the separator between
+    // it and the next one.
+    fn push_key(&mut self, height: usize, key: Key, pager: &mut Pager) -> Result<()> {
+        if self.levels[height].node.keys.len() < self.per_node {
+            self.levels[height].node.keys.push(key);
+            return Ok(());
+        }
+
+        let next = self.empty(height == 0, pager)?;
+        let level = &mut self.levels[height];
+        let node = std::mem::replace(&mut level.node, next);
+
+        match level.previous.replace((node, key)) {
+            Some((previous, separator)) => self.close(height, previous, Some(separator), pager),
+            None => Ok(()),
+        }
+    }
+
+    fn close(
+        &mut self,
+        height: usize,
+        mut node: Node,
+        separator: Option<Key>,
+        pager: &mut Pager,
+    ) -> Result<()> {
+        node.save(pager)?;
+
+        if height + 1 == self.levels.len() {
+            let parent = self.empty(false, pager)?;
+            self.levels.push(Level {
+                node: parent,
+                previous: None,
+            });
+        }
+
+        self.levels[height + 1].node.children.push(node.page);
+
+        match separator {
+            Some(key) => self.push_key(height + 1, key, pager),
+            None => Ok(()),
+        }
+    }
+
+    pub fn push(&mut self, key: Key, pager: &mut Pager) -> Result<()> {
+        self.push_key(0, key, pager)
+    }
+
+    // Merges an underfull last node into its neighbour when both fit in one
+    // node, or splits their keys evenly otherwise.
+    fn even_out(
+        &mut self,
+        height: usize,
+        mut previous: Node,
+        separator: Key,
+        mut node: Node,
+        pager: &mut Pager,
+    ) -> Result<()> {
+        if node.keys.len() >= self.order - 1 {
+            self.close(height, previous, Some(separator), pager)?;
+            return self.close(height, node, None, pager);
+        }
+
+        previous.keys.push(separator);
+        previous.keys.append(&mut node.keys);
+        previous.children.append(&mut node.children);
+
+        if previous.keys.len() < 2 * self.order {
+            self.pages.retain(|page| *page != node.page);
+            pager.free(node.page)?;
+            return self.close(height, previous, None, pager);
+        }
+
+        let mid = previous.keys.len() / 2;
+        node.keys = previous.keys.split_off(mid + 1);
+        let separator = previous.keys.pop().unwrap();
+
+        if !previous.leaf {
+            node.children = previous.children.split_off(mid + 1);
+        }
+
+        self.close(height, previous, Some(separator), pager)?;
+        self.close(height, node, None, pager)
+    }
+
+    pub fn finish(&mut self, pager: &mut Pager) -> Result<Node> {
+        let mut height = 0;
+
+        loop {
+            let level = &mut self.levels[height];
+            let node = level.node.clone();
+
+            match level.previous.take() {
+                Some((previous, separator)) => {
+                    self.even_out(height, previous, separator, node, pager)?
+                }
+                None => {
+                    let mut root = node;
+                    root.save(pager)?;
+                    return Ok(root);
+                }
+            }
+
+            height += 1;
+        }
+    }
+
+    // Frees every page the builder allocated, for a build that failed.
+    pub fn abandon(&mut self, pager: &mut Pager) -> Result<()> {
+        for page in std::mem::take(&mut self.pages) {
+            pager.free(page)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn _build(path: &str, count: u64, order: usize, fill_factor: f64) -> (Pager, Node) {
+        let mut pager = Pager::create(path).unwrap();
+        let mut builder = Builder::create(order, fill_factor, &mut pager).unwrap();
+
+        (0..count).for_each(|i| {
+            builder
+                .push(Key::create(&format!("{:05}", i), (i, 1)), &mut pager)
+                .unwrap()
+        });
+
+        let root = builder.finish(&mut pager).unwrap();
+        (pager, root)
+    }
+
+    fn _check(
+        pager: &Pager,
+        node: &Node,
+        order: usize,
+        root: bool,
+        values: &mut Vec<String>,
+    ) -> usize {
+        assert!(node.keys.len() < 2 * order);
+        assert!(root || node.keys.len() >= order - 1);
+
+        if node.leaf {
+            values.extend(node.keys.iter().map(|key| key.value[0].to_string()));
+            return 1;
+        }
+
+        assert_eq!(node.children.len(), node.keys.len() + 1);
+
+        let depths: Vec<usize> = node
+            .children
+            .iter()
+            .enumerate()
+            .map(|(i, child)| {
+                let depth = _check(
+                    pager,
+                    &Node::load(pager, *child).unwrap(),
+                    order,
+                    false,
+                    values,
+                );
+                if let Some(key) = node.keys.get(i) {
+                    values.push(key.value[0].to_string());
+                }
+                depth
+            })
+            .collect();
+
+        assert!(depths.iter().all(|depth| *depth == depths[0]));
+        depths[0] + 1
+    }
+
+    #[test]
+    fn finish() {
+        let path = "bulk_test_finish.idx";
+
+        for (count, order, fill_factor) in [
+            (0, 3, 1.0),
+            (4, 3, 1.0),
+            (5, 3, 1.0),
+            (6, 3, 1.0),
+            (100, 3, 1.0),
+            (1000, 3, 0.5),
+            (1000, 4, 0.7),
+            (777, 2, 1.0),
+        ]
+        .into_iter()
+        .chain((0..60).map(|count| (count, 2, 1.0)))
+        .chain((0..60).map(|count| (count, 3, 0.5)))
+        {
+            let (pager, root) = _build(path, count, order, fill_factor);
+
+            let mut values = Vec::new();
+            _check(&pager, &root, order, true, &mut values);
+
+            let expected: Vec<String> = (0..count).map(|i| format!("{:05}", i)).collect();
+            assert_eq!(values, expected);
+
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn abandon() {
+        let path = "bulk_test_abandon.idx";
+        let mut pager = Pager::create(path).unwrap();
+        let mut builder = Builder::create(2, 1.0, &mut pager).unwrap();
+
+        (0..100).for_each(|i| {
+            builder
+                .push(Key::create(&format!("{:05}", i), (i, 1)), &mut pager)
+                .unwrap()
+        });
+
+        builder.abandon(&mut pager).unwrap();
+        assert_eq!(pager.used_pages().unwrap(), 2);
+
+        drop(pager);
+        fs::remove_file(path).unwrap();
+    }
+
+    fn _first_leaf(pager: &Pager, node: &Node) -> Node {
+        match node.leaf {
+            true => node.clone(),
+            false => _first_leaf(pager, &Node::load(pager, node.children[0]).unwrap()),
+        }
+    }
+
+    #[test]
+    fn fill_factor() {
+        let path = "bulk_test_fill_factor.idx";
+
+        let (pager, root) = _build(path, 1000, 10, 1.0);
+        assert_eq!(_first_leaf(&pager, &root).keys.len(), 19);
+
+        drop(pager);
+        fs::remove_file(path).unwrap();
+
+        let (pager, root) = _build(path, 1000, 10, 0.5);
+        assert_eq!(_first_leaf(&pager, &root).keys.len(), 10);
+
+        drop(pager);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::{BTreeMap, HashMap};
+
+pub const DEFAULT_CAPACITY: Capacity = Capacity::Bytes(32 * 1024 * 1024);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Capacity {
+    Nodes(usize),
+    Bytes(usize),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+pub struct Cache {
+    capacity: Capacity,
+    entries: HashMap<u64, (Vec<u8>, u64)>,
+    recency: BTreeMap<u64, u64>,
+    tick: u64,
+    bytes: usize,
+    hits: u64,
+    misses: u64,
+}
+
+impl Cache {
+    pub fn create(capacity: Capacity) -> Cache {
+        Cache {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            bytes: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    fn touch(&mut self, page: u64) {
+        self.tick += 1;
+
+        if let Some((_, tick)) = self.entries.get_mut(&page) {
+            self.recency.remove(tick);
+            *tick = self.tick;
+            self.recency.insert(self.tick, page);
+        }
+    }
+
+    fn over_capacity(&self) -> bool {
+        match self.capacity {
+            Capacity::Nodes(limit) => self.entries.len() > limit,
+            Capacity::Bytes(limit) => self.bytes > limit,
+        }
+    }
+
+    pub fn get(&mut self, page: u64) -> Option<Vec<u8>> {
+        match self.entries.contains_key(&page) {
+            true => {
+                self.hits += 1;
+                self.touch(page);
+                self.entries.get(&page).map(|(data, _)| data.clone())
+            }
+            false => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn put(&mut self, page: u64, data: Vec<u8>) {
+        self.remove(page);
+
+        self.bytes += data.len();
+        self.entries.insert(page, (data, 0));
+        self.touch(page);
+
+        while self.over_capacity() {
+            match self.recency.pop_first() {
+                None => break,
+                Some((_, oldest)) => {
+                    let (data, _) = self.entries.remove(&oldest).unwrap();
+                    self.bytes -= data.len();
+                }
+            }
+        }
+    }
+
+    pub fn remove(&mut self, page: u64) {
+        if let Some((data, tick)) = self.entries.remove(&page) {
+            self.recency.remove(&tick);
+            self.bytes -= data.len();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.bytes = 0;
+    }
+
+    pub fn resize(&mut self, capacity: Capacity) {
+        self.capacity = capacity;
+
+        while self.over_capacity() {
+            match self.recency.first_key_value() {
+                None => break,
+                Some((_, oldest)) => self.remove(*oldest),
+            }
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            hits: self.hits,
+            misses: self.misses,
+            entries: self.entries.len(),
+            bytes: self.bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get() {
+        let mut cache = Cache::create(Capacity::Nodes(2));
+
+        assert_eq!(cache.get(1), None);
+
+        cache.put(1, vec![1, 2, 3]);
+        assert_eq!(cache.get(1), Some(vec![1, 2, 3]));
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        assert_eq!((stats.entries, stats.bytes), (1, 3));
+    }
+
+    #[test]
+    fn put() {
+        let mut cache = Cache::create(Capacity::Nodes(2));
+
+        cache.put(1, vec![1]);
+        cache.put(2, vec![2]);
+        cache.get(1);
+        cache.put(3, vec![3]);
+
+        assert_eq!(cache.get(2), None);
+        assert_eq!(cache.get(1), Some(vec![1]));
+        assert_eq!(cache.get(3), Some(vec![3]));
+
+        cache.put(3, vec![3, 3]);
+        assert_eq!(cache.stats().entries, 2);
+        assert_eq!(cache.stats().bytes, 3);
+    }
+
+    #[test]
+    fn bytes_capacity() {
+        let mut cache = Cache::create(Capacity::Bytes(10));
+
+        cache.put(1, vec![0; 4]);
+        cache.put(2, vec![0; 4]);
+        cache.put(3, vec![0; 4]);
+
+        assert_eq!(cache.stats().bytes, 8);
+        assert_eq!(cache.get(1), None);
+
+        cache.put(4, vec![0; 20]);
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn resize() {
+        let mut cache = Cache::create(Capacity::Nodes(3));
+
+        (1..=3).for_each(|page| cache.put(page, vec![page as u8]));
+        cache.resize(Capacity::Nodes(1));
+
+        assert_eq!(cache.stats().entries, 1);
+        assert_eq!(cache.get(3), Some(vec![3]));
+    }
+
+    #[test]
+    fn remove() {
+        let mut cache = Cache::create(Capacity::Nodes(3));
+
+        cache.put(1, vec![1]);
+        cache.remove(1);
+
+        assert_eq!(cache.get(1), None);
+        assert_eq!(cache.stats().bytes, 0);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::error::{Error, Result};
+
+pub const FORMAT_VERSION: u8 = 9;
+
+pub fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+pub fn write_signed(buf: &mut Vec<u8>, value: i64) {
+    write_varint(buf, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+pub fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+#[derive(Clone, Copy)]
+pub struct Checksum {
+    state: u64,
+}
+
+impl Checksum {
+    pub fn create() -> Checksum {
+        Checksum {
+            state: 0xcbf2_9ce4_8422_2325,
+        }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.state = (self.state ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.state
+    }
+}
+
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn create(buf: &'a [u8]) -> Reader<'a> {
+        Reader { buf, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.buf.len())
+            .ok_or_else(|| Error::Corrupt(String::from("truncated record")))?;
+
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_varint(&mut self) -> Result<u64> {
+        let mut value = 0;
+        let mut shift = 0;
+
+        loop {
+            let byte = self.read_u8()?;
+
+            if shift > 63 {
+                return Err(Error::Corrupt(String::from("varint overflow")));
+            }
+
+            value |= ((byte & 0x7f) as u64) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+
+            shift += 7;
+        }
+    }
+
+    pub fn read_signed(&mut self) -> Result<i64> {
+        let value = self.read_varint()?;
+        Ok(((value >> 1) as i64) ^ -((value & 1) as i64))
+    }
+
+    pub fn read_bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.read_varint()? as usize;
+        self.take(len)
+    }
+
+    pub fn read_string(&mut self) -> Result<String> {
+        String::from_utf8(self.read_bytes()?.to_vec())
+            .map_err(|_| Error::Corrupt(String::from("invalid utf-8 in record")))
+    }
+
+    pub fn read_version(&mut self) -> Result<()> {
+        match self.read_u8()? {
+            FORMAT_VERSION => Ok(()),
+            found => Err(Error::VersionMismatch {
+                found,
+                expected: FORMAT_VERSION,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_varint() {
+        let mut buf = Vec::new();
+
+        super::write_varint(&mut buf, 0);
+        super::write_varint(&mut buf, 127);
+        super::write_varint(&mut buf, 128);
+        super::write_varint(&mut buf, u64::MAX);
+
+        assert_eq!(buf.len(), 1 + 1 + 2 + 10);
+
+        let mut reader = Reader::create(&buf);
+        assert_eq!(reader.read_varint().unwrap(), 0);
+        assert_eq!(reader.read_varint().unwrap(), 127);
+        assert_eq!(reader.read_varint().unwrap(), 128);
+        assert_eq!(reader.read_varint().unwrap(), u64::MAX);
+        assert!(matches!(reader.read_varint(), Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn write_signed() {
+        let mut buf = Vec::new();
+
+        for value in [0, -1, 1, -64, 64, i64::MIN, i64::MAX] {
+            super::write_signed(&mut buf, value);
+        }
+
+        assert_eq!(buf[..5], [0, 1, 2, 127, 128]);
+
+        let mut reader = Reader::create(&buf);
+        for value in [0, -1, 1, -64, 64, i64::MIN, i64::MAX] {
+            assert_eq!(reader.read_signed().unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn write_bytes() {
+        let mut buf = Vec::new();
+
+        super::write_bytes(&mut buf, b"sample");
+        super::write_bytes(&mut buf, b"");
+
+        let mut reader = Reader::create(&buf);
+        assert_eq!(reader.read_bytes().unwrap(), b"sample");
+        assert_eq!(reader.read_bytes().unwrap(), b"");
+
+        let mut reader = Reader::create(&buf[..4]);
+        assert!(matches!(reader.read_bytes(), Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn checksum() {
+        let mut checksum = Checksum::create();
+        assert_eq!(checksum.finish(), 0xcbf2_9ce4_8422_2325);
+
+        checksum.update(b"a");
+        assert_eq!(checksum.finish(), 0xaf63_dc4c_8601_ec8c);
+
+        let mut split = Checksum::create();
+        split.update(b"sam");
+        split.update(b"ple");
+
+        let mut whole = Checksum::create();
+        whole.update(b"sample");
+        assert_eq!(split.finish(), whole.finish());
+    }
+
+    #[test]
+    fn read_version() {
+        assert!(Reader::create(&[FORMAT_VERSION]).read_version().is_ok());
+        assert!(matches!(
+            Reader::create(&[1]).read_version(),
+            Err(Error::VersionMismatch { found: 1, .. })
+        ));
+    }
+}
@@ -1,28 +1,224 @@
-use serde::{Serialize, Deserialize};
+use crate::error::Result;
+use crate::index::encoding::{write_varint, Reader};
+use crate::index::value::Value;
+use serde::Serialize;
+use std::cmp::Ordering;
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize)]
 pub struct Key {
-    pub value: String,
+    pub value: Vec<Value>,
     pub position: (u64, u64),
+    pub postings: Vec<(u64, u64)>,
+}
+
+pub trait Components {
+    fn components(self) -> Vec<String>;
+}
+
+impl Components for &str {
+    fn components(self) -> Vec<String> {
+        vec![self.to_string()]
+    }
+}
+
+impl Components for &String {
+    fn components(self) -> Vec<String> {
+        vec![self.clone()]
+    }
+}
+
+impl Components for &[&str] {
+    fn components(self) -> Vec<String> {
+        self.iter().map(|value| value.to_string()).collect()
+    }
+}
+
+impl<const N: usize> Components for [&str; N] {
+    fn components(self) -> Vec<String> {
+        self.iter().map(|value| value.to_string()).collect()
+    }
+}
+
+impl Components for (&str, &str) {
+    fn components(self) -> Vec<String> {
+        vec![self.0.to_string(), self.1.to_string()]
+    }
+}
+
+impl Components for (&str, &str, &str) {
+    fn components(self) -> Vec<String> {
+        vec![self.0.to_string(), self.1.to_string(), self.2.to_string()]
+    }
 }
 
 impl Key {
     pub fn create(value: &str, position: (u64, u64)) -> Key {
         Key {
-            value: value.to_string(),
+            value: vec![Value::from(value)],
             position,
+            postings: Vec::new(),
         }
     }
+
+    pub fn composite(value: Vec<Value>, position: (u64, u64)) -> Key {
+        Key {
+            value,
+            position,
+            postings: Vec::new(),
+        }
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        std::iter::once(self.position).chain(self.postings.iter().copied())
+    }
+
+    pub fn merge(&mut self, other: &Key) {
+        let mut positions: Vec<(u64, u64)> = self.positions().chain(other.positions()).collect();
+        positions.sort_unstable();
+        positions.dedup();
+
+        self.position = positions[0];
+        self.postings = positions.split_off(1);
+    }
+
+    pub fn cmp_prefix(&self, prefix: &[Value]) -> Ordering {
+        let len = self.value.len().min(prefix.len());
+        self.value[..len].cmp(prefix)
+    }
+
+    pub(crate) fn encode(&self, buf: &mut Vec<u8>) {
+        write_varint(buf, self.value.len() as u64);
+        self.value
+            .iter()
+            .for_each(|component| component.encode(buf));
+        write_varint(buf, self.position.0);
+        write_varint(buf, self.position.1);
+
+        // Postings are kept sorted, so deltas are small, but the field is public
+        // and an unsorted list has to round-trip too: the delta wraps around,
+        // as the sum does when decoding.
+        write_varint(buf, self.postings.len() as u64);
+        self.positions()
+            .zip(&self.postings)
+            .for_each(|(previous, posting)| {
+                write_varint(buf, posting.0.wrapping_sub(previous.0));
+                write_varint(buf, posting.1);
+            });
+    }
+
+    pub(crate) fn decode(reader: &mut Reader) -> Result<Key> {
+        let value = (0..reader.read_varint()?)
+            .map(|_| Value::decode(reader))
+            .collect::<Result<_>>()?;
+        let position = (reader.read_varint()?, reader.read_varint()?);
+
+        let mut offset = position.0;
+        let postings = (0..reader.read_varint()?)
+            .map(|_| {
+                offset = offset.wrapping_add(reader.read_varint()?);
+                Ok((offset, reader.read_varint()?))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Key {
+            value,
+            position,
+            postings,
+        })
+    }
 }
 
+#[cfg(test)]
 mod tests {
-    #[cfg(test)]
     use super::*;
 
+    fn _strings(values: &[&str]) -> Vec<Value> {
+        values.iter().map(|value| Value::from(*value)).collect()
+    }
+
     #[test]
     fn create() {
         let key = Key::create("Sample", (10, 20));
-        assert_eq!(key.value, "Sample");
+        assert_eq!(key.value, ["Sample"]);
         assert_eq!(key.position, (10, 20));
     }
+
+    #[test]
+    fn composite() {
+        let key = Key::composite(_strings(&["BR", "10"]), (10, 20));
+        assert_eq!(key.value, ["BR", "10"]);
+        assert_eq!(key.position, (10, 20));
+    }
+
+    #[test]
+    fn cmp_prefix() {
+        let key = Key::composite(vec![Value::from("BR"), Value::Int(10)], (0, 0));
+
+        assert_eq!(key.cmp_prefix(&_strings(&["BR"])), Ordering::Equal);
+        assert_eq!(key.cmp_prefix(&_strings(&["AR"])), Ordering::Greater);
+        assert_eq!(
+            key.cmp_prefix(&[Value::from("BR"), Value::Int(2)]),
+            Ordering::Greater
+        );
+        assert_eq!(
+            key.cmp_prefix(&[Value::from("BR"), Value::Int(10), Value::from("X")]),
+            Ordering::Less
+        );
+        assert_eq!(key.cmp_prefix(&[]), Ordering::Equal);
+    }
+
+    #[test]
+    fn encode() {
+        let mut buf = Vec::new();
+        Key::create("Sample", (10, 300)).encode(&mut buf);
+
+        assert_eq!(buf.len(), 1 + 1 + 1 + 6 + 1 + 2 + 1);
+
+        let key = Key::decode(&mut Reader::create(&buf)).unwrap();
+        assert_eq!(key.value, ["Sample"]);
+        assert_eq!(key.position, (10, 300));
+
+        let mut buf = Vec::new();
+        let value = vec![Value::from("BR"), Value::from(""), Value::UInt(10)];
+        Key::composite(value.clone(), (1, 2)).encode(&mut buf);
+
+        let key = Key::decode(&mut Reader::create(&buf)).unwrap();
+        assert_eq!(key.value, value);
+    }
+
+    #[test]
+    fn merge() {
+        let mut key = Key::create("Sample", (300, 10));
+        key.merge(&Key::create("Sample", (20, 5)));
+        key.merge(&Key::create("Sample", (300, 10)));
+
+        let mut other = Key::create("Sample", (1000, 7));
+        other.merge(&Key::create("Sample", (150, 2)));
+        key.merge(&other);
+
+        assert_eq!(key.position, (20, 5));
+        assert_eq!(key.postings, [(150, 2), (300, 10), (1000, 7)]);
+
+        let mut buf = Vec::new();
+        key.encode(&mut buf);
+
+        let decoded = Key::decode(&mut Reader::create(&buf)).unwrap();
+        assert_eq!(
+            decoded.positions().collect::<Vec<_>>(),
+            key.positions().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn encode_unsorted() {
+        let mut key = Key::create("Sample", (300, 10));
+        key.postings = vec![(20, 5), (u64::MAX, 1), (0, 2), (300, 10)];
+
+        let mut buf = Vec::new();
+        key.encode(&mut buf);
+
+        let decoded = Key::decode(&mut Reader::create(&buf)).unwrap();
+        assert_eq!(decoded.position, key.position);
+        assert_eq!(decoded.postings, key.postings);
+    }
 }
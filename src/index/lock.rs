@@ -0,0 +1,255 @@
+use crate::error::{Error, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, prelude::*};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_WAIT: Wait = Wait::Timeout(Duration::from_secs(10));
+
+const RETRY: Duration = Duration::from_millis(10);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wait {
+    NoWait,
+    Block,
+    Timeout(Duration),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Shared,
+    Exclusive,
+}
+
+// Flock can't convert a lock atomically: switching modes releases the old lock
+// before taking the new one. Each attempt to take the lock holds an exclusive
+// gate, which keeps other processes out until a failed switch has got the old
+// lock back. The gate is only held for one attempt, so waiting happens outside.
+// Only the exclusive holder writes its pid to the lock file, for waiters to
+// report, and clears it when it gives the lock up.
+pub struct Lock {
+    file: File,
+    gate: File,
+    index: String,
+    mode: Mode,
+    wait: Wait,
+    pinned: bool,
+}
+
+pub fn filename(index: &str) -> String {
+    format!("{}.lock", index)
+}
+
+fn gate(index: &str) -> String {
+    format!("{}.gate", index)
+}
+
+fn open(filename: &str) -> Result<File> {
+    Ok(OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(filename)?)
+}
+
+fn lock(file: &File, mode: Mode) -> io::Result<()> {
+    match mode {
+        Mode::Shared => file.lock_shared(),
+        Mode::Exclusive => file.lock(),
+    }
+}
+
+impl Lock {
+    pub fn acquire(index: &str, mode: Mode, wait: Wait) -> Result<Lock> {
+        let mut lock = Lock {
+            file: open(&filename(index))?,
+            gate: open(&gate(index))?,
+            index: index.to_string(),
+            // Nothing is held until the wait succeeds, so a failed attempt
+            // doesn't clear the holder's pid when dropped.
+            mode: Mode::Shared,
+            wait,
+            pinned: false,
+        };
+
+        lock.wait(mode, None)?;
+        Ok(lock)
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn set_wait(&mut self, wait: Wait) {
+        self.wait = wait;
+    }
+
+    // A pinned lock stays exclusive until unpinned, however many writes go
+    // through it.
+    pub fn pinned(&self) -> bool {
+        self.pinned
+    }
+
+    pub fn set_pinned(&mut self, pinned: bool) {
+        self.pinned = pinned;
+    }
+
+    pub fn switch(&mut self, mode: Mode) -> Result<()> {
+        match self.mode == mode {
+            true => Ok(()),
+            false => self.wait(mode, Some(self.mode)),
+        }
+    }
+
+    fn wait(&mut self, mode: Mode, held: Option<Mode>) -> Result<()> {
+        let start = Instant::now();
+
+        while !self.attempt(mode, held)? {
+            match self.wait {
+                Wait::Block => {}
+                Wait::Timeout(timeout) if start.elapsed() < timeout => {}
+                _ => {
+                    return Err(Error::Locked {
+                        path: self.index.clone(),
+                        pid: self.holder(),
+                    })
+                }
+            }
+
+            thread::sleep(RETRY);
+        }
+
+        self.mode = mode;
+
+        match (mode, held) {
+            (Mode::Exclusive, _) => {
+                self.file.set_len(0)?;
+                self.file.rewind()?;
+                write!(self.file, "{}", process::id())?;
+            }
+            (Mode::Shared, Some(Mode::Exclusive)) => self.file.set_len(0)?,
+            (Mode::Shared, _) => {}
+        }
+
+        Ok(())
+    }
+
+    fn attempt(&self, mode: Mode, held: Option<Mode>) -> Result<bool> {
+        self.gate.lock()?;
+
+        if held.is_some() {
+            self.file.unlock()?;
+        }
+
+        let attempt = match mode {
+            Mode::Shared => self.file.try_lock_shared(),
+            Mode::Exclusive => self.file.try_lock(),
+        };
+
+        let result = match (attempt, held) {
+            (Ok(()), _) => Ok(true),
+            (Err(fs::TryLockError::WouldBlock), None) => Ok(false),
+            (Err(fs::TryLockError::WouldBlock), Some(held)) => {
+                lock(&self.file, held).map(|_| false)
+            }
+            (Err(fs::TryLockError::Error(error)), _) => Err(error),
+        };
+
+        self.gate.unlock()?;
+        Ok(result?)
+    }
+
+    fn holder(&self) -> Option<u32> {
+        fs::read_to_string(filename(&self.index))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        if self.mode == Mode::Exclusive {
+            let _ = self.file.set_len(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acquire() {
+        let index = "lock_test_acquire.idx";
+
+        let first = Lock::acquire(index, Mode::Shared, Wait::NoWait).unwrap();
+        let second = Lock::acquire(index, Mode::Shared, Wait::NoWait).unwrap();
+
+        match Lock::acquire(index, Mode::Exclusive, Wait::NoWait) {
+            Err(Error::Locked { pid, .. }) => assert_eq!(pid, None),
+            _ => panic!("expected the index to be locked"),
+        }
+
+        drop(first);
+        drop(second);
+
+        let mut lock = Lock::acquire(index, Mode::Exclusive, Wait::NoWait).unwrap();
+
+        let start = Instant::now();
+        assert!(matches!(
+            Lock::acquire(
+                index,
+                Mode::Shared,
+                Wait::Timeout(Duration::from_millis(50))
+            ),
+            Err(Error::Locked { .. })
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(lock.holder(), Some(process::id()));
+
+        lock.switch(Mode::Shared).unwrap();
+        assert_eq!(lock.mode(), Mode::Shared);
+        assert_eq!(lock.holder(), None);
+        let reader = Lock::acquire(index, Mode::Shared, Wait::NoWait).unwrap();
+
+        lock.set_wait(Wait::NoWait);
+        assert!(matches!(
+            lock.switch(Mode::Exclusive),
+            Err(Error::Locked { .. })
+        ));
+        assert_eq!(lock.mode(), Mode::Shared);
+
+        drop(reader);
+        lock.switch(Mode::Exclusive).unwrap();
+        assert!(Lock::acquire(index, Mode::Shared, Wait::NoWait).is_err());
+
+        drop(lock);
+        fs::remove_file(filename(index)).unwrap();
+        fs::remove_file(gate(index)).unwrap();
+    }
+
+    #[test]
+    fn block() {
+        let index = "lock_test_block.idx";
+        let lock = Lock::acquire(index, Mode::Exclusive, Wait::NoWait).unwrap();
+
+        let waiter = thread::spawn(move || {
+            Lock::acquire(index, Mode::Exclusive, Wait::Block)
+                .unwrap()
+                .mode()
+        });
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(!waiter.is_finished());
+
+        drop(lock);
+        assert_eq!(waiter.join().unwrap(), Mode::Exclusive);
+
+        fs::remove_file(filename(index)).unwrap();
+        fs::remove_file(gate(index)).unwrap();
+    }
+}
@@ -1,25 +1,81 @@
+use crate::error::{Error, Result};
+use crate::index::encoding::{write_varint, Reader, FORMAT_VERSION};
 use crate::index::key::Key;
-use serde::{Deserialize, Serialize};
-use std::fs;
-use std::fs::File;
-use std::io::prelude::*;
-use uuid::Uuid;
+use crate::index::pager::Pager;
+use crate::index::value::Value;
+use serde_json::json;
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone)]
 pub struct Node {
     pub keys: Vec<Key>,
-    pub children: Vec<String>,
+    pub children: Vec<u64>,
     pub leaf: bool,
-    pub filename: String,
+    pub page: u64,
 }
 
 impl Node {
-    fn filename(path: &str) -> String {
-        format!("{}/{}.json", path, Uuid::new_v4().to_string())
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![FORMAT_VERSION, self.leaf as u8];
+
+        write_varint(&mut buf, self.keys.len() as u64);
+        self.keys.iter().for_each(|key| key.encode(&mut buf));
+
+        write_varint(&mut buf, self.children.len() as u64);
+        self.children
+            .iter()
+            .for_each(|child| write_varint(&mut buf, *child));
+
+        buf
     }
 
-    pub fn load(filename: &str) -> Node {
-        serde_json::from_slice(&fs::read(filename).unwrap()).unwrap()
+    pub fn decode(page: u64, buf: &[u8]) -> Result<Node> {
+        let mut reader = Reader::create(buf);
+        reader.read_version()?;
+
+        let leaf = reader.read_u8()? == 1;
+        let keys: Vec<Key> = (0..reader.read_varint()?)
+            .map(|_| Key::decode(&mut reader))
+            .collect::<Result<_>>()?;
+        let children: Vec<u64> = (0..reader.read_varint()?)
+            .map(|_| reader.read_varint())
+            .collect::<Result<_>>()?;
+
+        let expected = if leaf { 0 } else { keys.len() + 1 };
+
+        if children.len() != expected {
+            return Err(Error::Corrupt(format!(
+                "node at page {} has {} keys and {} children",
+                page,
+                keys.len(),
+                children.len()
+            )));
+        }
+
+        Ok(Node {
+            keys,
+            children,
+            leaf,
+            page,
+        })
+    }
+
+    pub fn load(pager: &Pager, page: u64) -> Result<Node> {
+        Node::decode(page, &pager.read(page)?)
+    }
+
+    pub fn to_json(&self, pager: &Pager) -> Result<serde_json::Value> {
+        let children = self
+            .children
+            .iter()
+            .map(|child| Node::load(pager, *child)?.to_json(pager))
+            .collect::<Result<Vec<serde_json::Value>>>()?;
+
+        Ok(json!({
+            "page": self.page,
+            "leaf": self.leaf,
+            "keys": self.keys,
+            "children": children,
+        }))
     }
 
     fn find_position(&self, key: &Key) -> usize {
@@ -32,91 +88,275 @@ impl Node {
             }
         }
 
-        if idx + 1 == self.keys.len() {
-            if key.value > self.keys[idx].value {
-                idx += 1;
-            }
+        if idx + 1 == self.keys.len() && key.value > self.keys[idx].value {
+            idx += 1;
         }
 
         idx
     }
 
-    pub fn save(&self) {
-        let mut file = File::create(&self.filename).unwrap();
-        file.write_all(serde_json::to_string(self).unwrap().as_bytes())
-            .unwrap();
+    pub fn save(&mut self, pager: &mut Pager) -> Result<()> {
+        self.page = pager.relocate(self.page)?;
+        pager.write(self.page, &self.encode())
     }
 
-    fn add_key(&mut self, idx: usize, key: Key) {
-        if self.keys.len() == 0 {
+    // Saving a child may move it to a new page under copy-on-write, in which
+    // case the pointer to it has to be rewritten as well.
+    fn relink(&mut self, previous: u64, child: &Node, pager: &mut Pager) -> Result<()> {
+        if child.page == previous {
+            return Ok(());
+        }
+
+        if let Some(page) = self.children.iter_mut().find(|page| **page == previous) {
+            *page = child.page;
+        }
+
+        self.save(pager)
+    }
+
+    fn add_key(&mut self, idx: usize, key: Key, pager: &mut Pager) -> Result<()> {
+        if self.keys.is_empty() {
             self.keys.push(key);
         } else {
             self.keys.insert(idx, key);
         }
-        self.save();
+        self.save(pager)
     }
 
-    pub fn empty(order: usize, leaf: bool, path: &str) -> Node {
-        Node {
+    pub fn empty(order: usize, leaf: bool, pager: &mut Pager) -> Result<Node> {
+        Ok(Node {
             keys: Vec::with_capacity(2 * order - 1),
             children: Vec::with_capacity(2 * order),
             leaf,
-            filename: Node::filename(path),
-        }
+            page: pager.allocate()?,
+        })
     }
 
     pub fn is_full(&self, order: usize) -> bool {
         self.keys.len() == 2 * order - 1
     }
 
-    pub fn split(&mut self, pivot: usize, order: usize, path: &str) {
-        let left = &mut Node::load(&self.children[pivot]);
+    pub fn split(&mut self, pivot: usize, order: usize, pager: &mut Pager) -> Result<()> {
+        let left = &mut Node::load(pager, self.children[pivot])?;
         let key = left.keys[order - 1].clone();
 
-        let right = Node {
+        let mut right = Node {
             keys: left.keys[order..left.keys.len()].to_owned(),
             children: match left.leaf {
                 true => Vec::with_capacity(2 * order),
                 false => left.children[order..left.children.len()].to_owned(),
             },
             leaf: left.leaf,
-            filename: Node::filename(path),
+            page: pager.allocate()?,
         };
 
-        right.save();
+        right.save(pager)?;
 
         left.keys.resize(order - 1, Key::create("", (0, 0)));
 
         if !left.leaf {
-            left.children.resize(order, String::from(""));
+            left.children.resize(order, 0);
         }
 
-        left.save();
+        left.save(pager)?;
 
         self.keys.insert(pivot, key);
-        self.children.insert(pivot + 1, right.filename);
+        self.children[pivot] = left.page;
+        self.children.insert(pivot + 1, right.page);
 
-        self.save()
+        self.save(pager)
     }
 
-    pub fn insert(&mut self, key: Key, order: usize, path: &str) {
-        if self.leaf {
-            self.add_key(self.find_position(&key), key.clone());
+    pub fn insert(&mut self, key: Key, order: usize, pager: &mut Pager) -> Result<()> {
+        if let Ok(idx) = self.locate(&key.value) {
+            self.keys[idx].merge(&key);
+            self.save(pager)
+        } else if self.leaf {
+            self.add_key(self.find_position(&key), key.clone(), pager)
         } else {
-            let mut idx = self.find_position(&key);
+            let idx = self.find_position(&key);
 
-            if Node::load(&self.children[idx]).is_full(order) {
-                self.split(idx, order, path);
-                idx = self.find_position(&key);
+            if Node::load(pager, self.children[idx])?.is_full(order) {
+                self.split(idx, order, pager)?;
+                return self.insert(key, order, pager);
             }
 
-            Node::load(&self.children[idx]).insert(key, order, path);
+            let mut child = Node::load(pager, self.children[idx])?;
+            let previous = child.page;
+            child.insert(key, order, pager)?;
+            self.relink(previous, &child, pager)
+        }
+    }
+
+    pub fn remove(&self, pager: &mut Pager) -> Result<()> {
+        pager.retire(self.page)
+    }
+
+    pub fn remove_all(&self, pager: &mut Pager) -> Result<()> {
+        for child in &self.children {
+            Node::load(pager, *child)?.remove_all(pager)?;
         }
+
+        self.remove(pager)
+    }
+
+    fn locate(&self, value: &[Value]) -> std::result::Result<usize, usize> {
+        self.keys
+            .binary_search_by(|key| key.value.as_slice().cmp(value))
+    }
+
+    fn max_key(&self, pager: &Pager) -> Result<Key> {
+        match self.leaf {
+            true => Ok(self.keys[self.keys.len() - 1].clone()),
+            false => Node::load(pager, self.children[self.children.len() - 1])?.max_key(pager),
+        }
+    }
+
+    fn min_key(&self, pager: &Pager) -> Result<Key> {
+        match self.leaf {
+            true => Ok(self.keys[0].clone()),
+            false => Node::load(pager, self.children[0])?.min_key(pager),
+        }
+    }
+
+    fn merge(&mut self, idx: usize, pager: &mut Pager) -> Result<Node> {
+        let mut left = Node::load(pager, self.children[idx])?;
+        let mut right = Node::load(pager, self.children[idx + 1])?;
+
+        left.keys.push(self.keys.remove(idx));
+        left.keys.append(&mut right.keys);
+        left.children.append(&mut right.children);
+        self.children.remove(idx + 1);
+
+        right.remove(pager)?;
+        left.save(pager)?;
+        self.children[idx] = left.page;
+        self.save(pager)?;
+
+        Ok(left)
+    }
+
+    fn borrow_left(&mut self, idx: usize, pager: &mut Pager) -> Result<Node> {
+        let mut sibling = Node::load(pager, self.children[idx - 1])?;
+        let mut child = Node::load(pager, self.children[idx])?;
+
+        let key = std::mem::replace(&mut self.keys[idx - 1], sibling.keys.pop().unwrap());
+        child.keys.insert(0, key);
+
+        if !child.leaf {
+            child.children.insert(0, sibling.children.pop().unwrap());
+        }
+
+        sibling.save(pager)?;
+        child.save(pager)?;
+        self.children[idx - 1] = sibling.page;
+        self.children[idx] = child.page;
+        self.save(pager)?;
+
+        Ok(child)
+    }
+
+    fn borrow_right(&mut self, idx: usize, pager: &mut Pager) -> Result<Node> {
+        let mut sibling = Node::load(pager, self.children[idx + 1])?;
+        let mut child = Node::load(pager, self.children[idx])?;
+
+        let key = std::mem::replace(&mut self.keys[idx], sibling.keys.remove(0));
+        child.keys.push(key);
+
+        if !child.leaf {
+            child.children.push(sibling.children.remove(0));
+        }
+
+        sibling.save(pager)?;
+        child.save(pager)?;
+        self.children[idx] = child.page;
+        self.children[idx + 1] = sibling.page;
+        self.save(pager)?;
+
+        Ok(child)
+    }
+
+    fn fill(&mut self, idx: usize, order: usize, pager: &mut Pager) -> Result<Node> {
+        let child = Node::load(pager, self.children[idx])?;
+
+        if child.keys.len() >= order {
+            Ok(child)
+        } else if idx > 0 && Node::load(pager, self.children[idx - 1])?.keys.len() >= order {
+            self.borrow_left(idx, pager)
+        } else if idx + 1 < self.children.len()
+            && Node::load(pager, self.children[idx + 1])?.keys.len() >= order
+        {
+            self.borrow_right(idx, pager)
+        } else if idx + 1 < self.children.len() {
+            self.merge(idx, pager)
+        } else {
+            self.merge(idx - 1, pager)
+        }
+    }
+
+    pub fn delete(
+        &mut self,
+        value: &[Value],
+        order: usize,
+        pager: &mut Pager,
+    ) -> Result<Option<Key>> {
+        match self.locate(value) {
+            Ok(idx) if self.leaf => {
+                let key = self.keys.remove(idx);
+                self.save(pager)?;
+                Ok(Some(key))
+            }
+            Ok(idx) => {
+                let mut left = Node::load(pager, self.children[idx])?;
+                let mut right = Node::load(pager, self.children[idx + 1])?;
+
+                if left.keys.len() >= order {
+                    let predecessor = left.max_key(pager)?;
+                    let key = std::mem::replace(&mut self.keys[idx], predecessor.clone());
+                    left.delete(&predecessor.value, order, pager)?;
+                    self.children[idx] = left.page;
+                    self.save(pager)?;
+                    Ok(Some(key))
+                } else if right.keys.len() >= order {
+                    let successor = right.min_key(pager)?;
+                    let key = std::mem::replace(&mut self.keys[idx], successor.clone());
+                    right.delete(&successor.value, order, pager)?;
+                    self.children[idx + 1] = right.page;
+                    self.save(pager)?;
+                    Ok(Some(key))
+                } else {
+                    self.descend(|node, pager| node.merge(idx, pager), value, order, pager)
+                }
+            }
+            Err(_) if self.leaf => Ok(None),
+            Err(idx) => self.descend(
+                |node, pager| node.fill(idx, order, pager),
+                value,
+                order,
+                pager,
+            ),
+        }
+    }
+
+    fn descend(
+        &mut self,
+        child: impl FnOnce(&mut Node, &mut Pager) -> Result<Node>,
+        value: &[Value],
+        order: usize,
+        pager: &mut Pager,
+    ) -> Result<Option<Key>> {
+        let mut child = child(self, pager)?;
+        let previous = child.page;
+        let key = child.delete(value, order, pager)?;
+        self.relink(previous, &child, pager)?;
+        Ok(key)
     }
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
     const _PLACEHOLDER: (u64, u64) = (0, 0);
 
     fn _create_key(value: &str) -> Key {
@@ -125,36 +365,36 @@ mod tests {
 
     #[test]
     fn add_key() {
-        let path = "node_test_add_key";
-        fs::create_dir(path).unwrap();
+        let path = "node_test_add_key.idx";
+        let mut pager = Pager::create(path).unwrap();
 
-        let mut node = Node::empty(3, true, path);
+        let mut node = Node::empty(3, true, &mut pager).unwrap();
 
         let first_key = _create_key("A");
         let second_key = _create_key("B");
         let last_key = _create_key("C");
 
-        node.add_key(0, _create_key("A"));
+        node.add_key(0, _create_key("A"), &mut pager).unwrap();
         assert_eq!(node.keys[0].value, first_key.value);
 
-        node.add_key(1, _create_key("C"));
+        node.add_key(1, _create_key("C"), &mut pager).unwrap();
         assert_eq!(node.keys[1].value, last_key.value);
 
-        node.add_key(1, _create_key("B"));
+        node.add_key(1, _create_key("B"), &mut pager).unwrap();
         assert_eq!(node.keys[1].value, second_key.value);
         assert_eq!(node.keys[2].value, last_key.value);
 
-        fs::remove_dir_all(path).unwrap();
+        fs::remove_file(path).unwrap();
     }
 
     #[test]
     fn find_position() {
-        let path = "node_test_find_position";
-        fs::create_dir(path).unwrap();
-        let mut node = Node::empty(3, true, path);
+        let path = "node_test_find_position.idx";
+        let mut pager = Pager::create(path).unwrap();
+        let mut node = Node::empty(3, true, &mut pager).unwrap();
 
-        vec!["B", "D", "F"].iter().enumerate().for_each(|(i, s)| {
-            node.add_key(i, _create_key(s));
+        ["B", "D", "F"].iter().enumerate().for_each(|(i, s)| {
+            node.add_key(i, _create_key(s), &mut pager).unwrap();
         });
 
         assert_eq!(node.find_position(&_create_key("A")), 0);
@@ -162,84 +402,228 @@ mod tests {
         assert_eq!(node.find_position(&_create_key("E")), 2);
         assert_eq!(node.find_position(&_create_key("G")), 3);
 
-        fs::remove_dir_all(path).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn encode() {
+        let path = "node_test_encode.idx";
+        let mut pager = Pager::create(path).unwrap();
+        let mut node = Node::empty(3, false, &mut pager).unwrap();
+
+        node.keys = vec![_create_key("A"), Key::create("B", (1 << 40, 12))];
+        node.children = vec![2, 3, 300];
+
+        let decoded = Node::decode(node.page, &node.encode()).unwrap();
+
+        assert!(!decoded.leaf);
+        assert_eq!(decoded.page, node.page);
+        assert_eq!(decoded.keys[0].value, ["A"]);
+        assert_eq!(decoded.keys[1].value, ["B"]);
+        assert_eq!(decoded.keys[1].position, (1 << 40, 12));
+        assert_eq!(decoded.children, [2, 3, 300]);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn decode_version() {
+        let mut buf = Node {
+            keys: Vec::new(),
+            children: Vec::new(),
+            leaf: true,
+            page: 2,
+        }
+        .encode();
+
+        buf[0] = FORMAT_VERSION + 1;
+        assert!(matches!(
+            Node::decode(2, &buf),
+            Err(Error::VersionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn decode_corrupt() {
+        let mut node = Node {
+            keys: vec![_create_key("A")],
+            children: vec![2, 3],
+            leaf: false,
+            page: 2,
+        };
+
+        let buf = node.encode();
+        assert!(matches!(
+            Node::decode(2, &buf[..buf.len() - 2]),
+            Err(Error::Corrupt(_))
+        ));
+
+        node.children.pop();
+        assert!(matches!(
+            Node::decode(2, &node.encode()),
+            Err(Error::Corrupt(_))
+        ));
     }
 
     #[test]
     fn empty() {
-        let path = "node_test_empty";
-        fs::create_dir(path).unwrap();
+        let path = "node_test_empty.idx";
+        let mut pager = Pager::create(path).unwrap();
         let order = 3;
-        let node = Node::empty(3, true, path);
-        
+        let node = Node::empty(3, true, &mut pager).unwrap();
 
         assert_eq!(node.keys.capacity(), 2 * order - 1);
         assert_eq!(node.children.capacity(), 2 * order);
 
-        fs::remove_dir_all(path).unwrap();
+        fs::remove_file(path).unwrap();
     }
 
     #[test]
     fn is_full() {
         let order = 2;
-        let path = "node_test_is_full";
-        fs::create_dir(path).unwrap();
-        let mut node = Node::empty(2, true, path);
+        let path = "node_test_is_full.idx";
+        let mut pager = Pager::create(path).unwrap();
+        let mut node = Node::empty(2, true, &mut pager).unwrap();
 
-        vec!["A", "B"].iter().enumerate().for_each(|(i, s)| {
-            node.add_key(i, _create_key(s));
+        ["A", "B"].iter().enumerate().for_each(|(i, s)| {
+            node.add_key(i, _create_key(s), &mut pager).unwrap();
         });
 
         assert!(!node.is_full(order));
 
-        node.add_key(2, _create_key("C"));
+        node.add_key(2, _create_key("C"), &mut pager).unwrap();
 
         assert!(node.is_full(order));
 
-        fs::remove_dir_all(path).unwrap();
+        fs::remove_file(path).unwrap();
     }
 
     #[test]
     fn split() {
         let order = 3;
-        let path = "node_test_split";
-        fs::create_dir(path).unwrap();
-        let mut node = Node::empty(order, true, path);
+        let path = "node_test_split.idx";
+        let mut pager = Pager::create(path).unwrap();
+        let mut node = Node::empty(order, true, &mut pager).unwrap();
 
-        vec!["A", "B", "C", "D", "E"]
+        ["A", "B", "C", "D", "E"]
             .iter()
             .enumerate()
             .for_each(|(i, s)| {
-                node.add_key(i, _create_key(s));
+                node.add_key(i, _create_key(s), &mut pager).unwrap();
             });
 
-        let mut father = Node::empty(order, false, &path);
-        father.children.push(node.filename);
-        father.split(0, order, path);
+        let mut father = Node::empty(order, false, &mut pager).unwrap();
+        father.children.push(node.page);
+        father.split(0, order, &mut pager).unwrap();
 
         assert_eq!(father.keys.len(), 1);
-        assert_eq!(Node::load(&father.children[0]).keys.len(), 2);
-        assert_eq!(Node::load(&father.children[1]).keys.len(), 2);
-
-        fs::remove_dir_all(path).unwrap();
+        assert_eq!(
+            Node::load(&pager, father.children[0]).unwrap().keys.len(),
+            2
+        );
+        assert_eq!(
+            Node::load(&pager, father.children[1]).unwrap().keys.len(),
+            2
+        );
+
+        fs::remove_file(path).unwrap();
     }
 
     #[test]
     fn insert() {
         let order = 3;
-        let path = "node_test_insert";
-        fs::create_dir(path).unwrap();
-        let mut node = Node::empty(order, true, path);
+        let path = "node_test_insert.idx";
+        let mut pager = Pager::create(path).unwrap();
+        let mut node = Node::empty(order, true, &mut pager).unwrap();
 
-        vec!["A", "Z", "C", "J", "E"].iter().for_each(|s| {
-            node.insert(_create_key(s), order, path);
+        ["A", "Z", "C", "J", "E"].iter().for_each(|s| {
+            node.insert(_create_key(s), order, &mut pager).unwrap();
         });
 
-        vec!["A", "C", "E", "J", "Z"]
+        ["A", "C", "E", "J", "Z"]
+            .iter()
+            .enumerate()
+            .for_each(|(i, s)| assert_eq!(node.keys[i].value, [*s]));
+
+        node.insert(Key::create("C", (10, 1)), order, &mut pager)
+            .unwrap();
+        assert_eq!(node.keys.len(), 5);
+        assert_eq!(node.keys[1].postings, [(10, 1)]);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn merge() {
+        let order = 3;
+        let path = "node_test_merge.idx";
+        let mut pager = Pager::create(path).unwrap();
+        let mut node = Node::empty(order, true, &mut pager).unwrap();
+
+        ["A", "B", "C", "D", "E"]
             .iter()
             .enumerate()
-            .for_each(|(i, s)| assert_eq!(node.keys[i].value, s.to_string()));
+            .for_each(|(i, s)| {
+                node.add_key(i, _create_key(s), &mut pager).unwrap();
+            });
+
+        let mut father = Node::empty(order, false, &mut pager).unwrap();
+        father.children.push(node.page);
+        father.split(0, order, &mut pager).unwrap();
+
+        let used_pages = pager.used_pages().unwrap();
+        let merged = father.merge(0, &mut pager).unwrap();
+
+        assert!(father.keys.is_empty());
+        assert_eq!(father.children.len(), 1);
+        assert_eq!(merged.keys.len(), 5);
+        assert_eq!(pager.used_pages().unwrap(), used_pages - 1);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn delete() {
+        let order = 2;
+        let path = "node_test_delete.idx";
+        let mut pager = Pager::create(path).unwrap();
+        let mut node = Node::empty(order, true, &mut pager).unwrap();
+
+        ["A", "C", "E", "G", "I", "K", "M"].iter().for_each(|s| {
+            if node.is_full(order) {
+                let mut father = Node::empty(order, false, &mut pager).unwrap();
+                father.children.push(node.page);
+                father.split(0, order, &mut pager).unwrap();
+                node = father;
+            }
+            node.insert(_create_key(s), order, &mut pager).unwrap();
+        });
+
+        assert!(node
+            .delete(&[Value::from("B")], order, &mut pager)
+            .unwrap()
+            .is_none());
+
+        ["G", "A", "M", "E"].iter().for_each(|s| {
+            match node.delete(&[Value::from(*s)], order, &mut pager).unwrap() {
+                None => panic!("{} not found!", s),
+                Some(key) => assert_eq!(key.value, [*s]),
+            }
+        });
+
+        let remaining: Vec<String> = node
+            .children
+            .iter()
+            .flat_map(|page| Node::load(&pager, *page).unwrap().keys)
+            .map(|key| key.value[0].to_string())
+            .chain(node.keys.iter().map(|key| key.value[0].to_string()))
+            .collect();
+
+        assert_eq!(remaining.len(), 3);
+        ["C", "I", "K"]
+            .iter()
+            .for_each(|s| assert!(remaining.contains(&s.to_string())));
 
-        fs::remove_dir_all(path).unwrap();
+        fs::remove_file(path).unwrap();
     }
 }
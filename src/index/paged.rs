@@ -0,0 +1,206 @@
+use crate::error::{Error, Result};
+use crate::index::encoding::{write_varint, Reader, FORMAT_VERSION};
+use crate::index::lock::{Lock, Mode, Wait, DEFAULT_WAIT};
+use crate::index::pager::{Pager, META_PAGE};
+use crate::index::schema::Schema;
+use crate::index::source::Source;
+use crate::index::wal;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+pub struct Metadata {
+    pub root: u64,
+    pub order: usize,
+    pub schema: Schema,
+    pub source: Option<Source>,
+    pub retired: Vec<u64>,
+}
+
+impl Metadata {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![FORMAT_VERSION];
+        write_varint(&mut buf, self.root);
+        write_varint(&mut buf, self.order as u64);
+        self.schema.encode(&mut buf);
+
+        match &self.source {
+            Some(source) => {
+                buf.push(1);
+                source.encode(&mut buf);
+            }
+            None => buf.push(0),
+        }
+
+        write_varint(&mut buf, self.retired.len() as u64);
+        self.retired
+            .iter()
+            .for_each(|page| write_varint(&mut buf, *page));
+
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Metadata> {
+        let mut reader = Reader::create(buf);
+        reader.read_version()?;
+
+        let root = reader.read_varint()?;
+        let order = reader.read_varint()? as usize;
+
+        if order < 2 {
+            return Err(Error::Corrupt(format!("invalid order {}", order)));
+        }
+
+        let schema = Schema::decode(&mut reader)?;
+
+        let source = match reader.read_u8()? {
+            0 => None,
+            _ => Some(Source::decode(&mut reader)?),
+        };
+
+        let retired = (0..reader.read_varint()?)
+            .map(|_| reader.read_varint())
+            .collect::<Result<_>>()?;
+
+        Ok(Metadata {
+            root,
+            order,
+            schema,
+            source,
+            retired,
+        })
+    }
+
+    pub fn load(pager: &Pager) -> Result<Metadata> {
+        Metadata::decode(&pager.read(META_PAGE)?)
+    }
+}
+
+// Creates the directory and file of a new index, returning them locked for
+// the caller to write its first root and metadata.
+pub fn create(path: &str, filename: &str, order: usize, schema: &Schema) -> Result<(Lock, Pager)> {
+    if order < 2 {
+        return Err(Error::InvalidOrder(order));
+    }
+
+    schema.validate()?;
+    fs::create_dir(path)?;
+
+    let lock = Lock::acquire(filename, Mode::Exclusive, DEFAULT_WAIT)?;
+    Ok((lock, Pager::create(filename)?))
+}
+
+pub fn open(filename: &str, wait: Wait) -> Result<(Lock, Pager, Metadata)> {
+    // Replaying a WAL left by a crashed writer rewrites the index.
+    let mode = match Path::new(&wal::filename(filename)).exists() {
+        true => Mode::Exclusive,
+        false => Mode::Shared,
+    };
+
+    let lock = Lock::acquire(filename, mode, wait)?;
+    let pager = Pager::open(filename)?;
+    let metadata = Metadata::load(&pager)?;
+
+    Ok((lock, pager, metadata))
+}
+
+// A tree kept in a pager file. Readers hold a shared lock on the index for as
+// long as the tree is open. Writes take it exclusively and give it back once
+// nothing is left to flush. The lock's gate keeps other processes from
+// committing during a switch; the refresh afterwards is a cheap check that
+// none did.
+pub trait Paged: Sized {
+    // What a rolled back transaction has to put back besides the pages.
+    type Saved;
+
+    fn pager(&self) -> &Pager;
+
+    fn pager_mut(&mut self) -> &mut Pager;
+
+    fn lock(&self) -> &Mutex<Lock>;
+
+    // Rereads the root and metadata after the file changed underneath.
+    fn reload(&mut self) -> Result<()>;
+
+    fn begin(&mut self) -> Self::Saved;
+
+    // Runs last inside a transaction whose operation succeeded.
+    fn finish(&mut self) -> Result<()>;
+
+    fn restore(&mut self, saved: Self::Saved);
+
+    fn guard(&self) -> MutexGuard<'_, Lock> {
+        self.lock().lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn refresh(&mut self) -> Result<()> {
+        if self.pager_mut().refresh()? {
+            self.reload()?;
+        }
+
+        Ok(())
+    }
+
+    fn switch(&mut self, mode: Mode) -> Result<()> {
+        let switched = self.guard().switch(mode);
+
+        self.refresh()?;
+        switched
+    }
+
+    fn mode(&self) -> Mode {
+        self.guard().mode()
+    }
+
+    fn upgrade(&mut self) -> Result<()> {
+        match self.mode() {
+            Mode::Shared => self.switch(Mode::Exclusive),
+            Mode::Exclusive => Ok(()),
+        }
+    }
+
+    fn downgrade(&mut self) -> Result<()> {
+        match self.mode() {
+            Mode::Exclusive if self.pager().dirty_pages() == 0 && !self.guard().pinned() => {
+                self.switch(Mode::Shared)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Keeps the exclusive lock across every write `operation` makes, instead of
+    // switching in and out around each of them.
+    fn batch<T>(&mut self, operation: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.upgrade()?;
+
+        let pinned = self.guard().pinned();
+        self.guard().set_pinned(true);
+        let result = operation(self);
+        self.guard().set_pinned(pinned);
+
+        self.downgrade()?;
+        result
+    }
+
+    fn atomic<T>(&mut self, operation: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.upgrade()?;
+        let result = self.transaction(operation);
+        self.downgrade()?;
+        result
+    }
+
+    fn transaction<T>(&mut self, operation: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let saved = self.begin();
+        self.pager_mut().begin();
+
+        match operation(self).and_then(|value| self.finish().map(|_| value)) {
+            Ok(value) => self.pager_mut().commit().map(|_| value),
+            Err(error) => {
+                self.pager_mut().rollback();
+                self.restore(saved);
+                self.reload()?;
+                Err(error)
+            }
+        }
+    }
+}
@@ -0,0 +1,802 @@
+use crate::error::{Error, Result};
+use crate::index::cache::{Cache, Capacity, Stats, DEFAULT_CAPACITY};
+use crate::index::wal::{self, Frame};
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+pub const PAGE_SIZE: usize = 4096;
+pub const META_PAGE: u64 = 1;
+
+const MAGIC: &[u8; 8] = b"CSVINDEX";
+const FILE_HEADER: usize = 36;
+const PAGE_HEADER: usize = 12;
+
+struct Undo {
+    pages: HashMap<u64, Option<Vec<u8>>>,
+    page_count: u64,
+    free_head: u64,
+    fresh: HashSet<u64>,
+    retired: Vec<u64>,
+}
+
+// Readers share the pager behind `&self`, so the file cursor and the cache sit
+// behind mutexes. Neither holds state a panic could leave half-updated, so a
+// poisoned lock is simply taken over.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub struct Pager {
+    file: Mutex<File>,
+    wal: String,
+    page_size: usize,
+    page_count: u64,
+    free_head: u64,
+    generation: u64,
+    cache: Mutex<Cache>,
+    write_back: bool,
+    copy_on_write: bool,
+    dirty: HashMap<u64, Vec<u8>>,
+    transaction: Option<Undo>,
+}
+
+impl Pager {
+    pub fn create(filename: &str) -> Result<Pager> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(filename)?;
+
+        let wal = wal::filename(filename);
+        wal::remove(&wal)?;
+
+        let mut pager = Pager {
+            file: Mutex::new(file),
+            wal,
+            page_size: PAGE_SIZE,
+            page_count: 1,
+            free_head: 0,
+            generation: 0,
+            cache: Mutex::new(Cache::create(DEFAULT_CAPACITY)),
+            write_back: false,
+            copy_on_write: false,
+            dirty: HashMap::new(),
+            transaction: None,
+        };
+
+        pager.save_header()?;
+        pager.allocate()?;
+        Ok(pager)
+    }
+
+    pub fn open(filename: &str) -> Result<Pager> {
+        let mut file = OpenOptions::new().read(true).write(true).open(filename)?;
+        let wal = wal::filename(filename);
+
+        if let Some(pages) = wal::read(&wal)? {
+            Pager::apply(&mut file, &pages)?;
+        }
+
+        wal::remove(&wal)?;
+
+        let header = Pager::read_header(&mut file)?;
+
+        if &header[0..8] != MAGIC {
+            return Err(Error::Corrupt(format!("{} is not an index file", filename)));
+        }
+
+        let page_size = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+        let page_count = u64::from_le_bytes(header[12..20].try_into().unwrap());
+        let len = file.metadata()?.len();
+
+        if page_size <= FILE_HEADER.max(PAGE_HEADER) {
+            return Err(Error::Corrupt(format!("invalid page size {}", page_size)));
+        }
+
+        if (page_size as u64)
+            .checked_mul(page_count)
+            .is_none_or(|size| size > len)
+        {
+            return Err(Error::Corrupt(format!(
+                "{} pages of {} bytes don't fit in {}",
+                page_count, page_size, filename
+            )));
+        }
+
+        Ok(Pager {
+            file: Mutex::new(file),
+            wal,
+            page_size,
+            page_count,
+            free_head: u64::from_le_bytes(header[20..28].try_into().unwrap()),
+            generation: u64::from_le_bytes(header[28..36].try_into().unwrap()),
+            cache: Mutex::new(Cache::create(DEFAULT_CAPACITY)),
+            write_back: false,
+            copy_on_write: false,
+            dirty: HashMap::new(),
+            transaction: None,
+        })
+    }
+
+    fn read_header(file: &mut File) -> Result<[u8; FILE_HEADER]> {
+        let mut header = [0; FILE_HEADER];
+        file.rewind()?;
+        file.read_exact(&mut header)?;
+        Ok(header)
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // Picks up commits made through other handles: replays a WAL they left
+    // behind and, when the generation moved, re-reads the header and drops
+    // every cached page.
+    pub fn refresh(&mut self) -> Result<bool> {
+        let file = self.file.get_mut().unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(pages) = wal::read(&self.wal)? {
+            Pager::apply(file, &pages)?;
+        }
+
+        wal::remove(&self.wal)?;
+
+        let header = Pager::read_header(file)?;
+        let generation = u64::from_le_bytes(header[28..36].try_into().unwrap());
+
+        if generation == self.generation {
+            return Ok(false);
+        }
+
+        self.page_count = u64::from_le_bytes(header[12..20].try_into().unwrap());
+        self.free_head = u64::from_le_bytes(header[20..28].try_into().unwrap());
+        self.generation = generation;
+        lock(&self.cache).clear();
+        Ok(true)
+    }
+
+    fn apply(file: &mut File, pages: &[Frame]) -> Result<()> {
+        let page_size = pages.first().map_or(PAGE_SIZE, |(_, buf)| buf.len()) as u64;
+
+        for (page, buf) in pages {
+            file.seek(SeekFrom::Start(page * page_size))?;
+            file.write_all(buf)?;
+        }
+
+        file.sync_data()?;
+        Ok(())
+    }
+
+    pub fn begin(&mut self) {
+        self.transaction = Some(Undo {
+            pages: HashMap::new(),
+            page_count: self.page_count,
+            free_head: self.free_head,
+            fresh: HashSet::new(),
+            retired: Vec::new(),
+        });
+    }
+
+    pub fn commit(&mut self) -> Result<()> {
+        self.generation += 1;
+        self.save_header()?;
+        self.transaction = None;
+
+        match self.write_back {
+            true => Ok(()),
+            false => self.flush(),
+        }
+    }
+
+    pub fn rollback(&mut self) {
+        if let Some(undo) = self.transaction.take() {
+            let touched = !undo.pages.is_empty();
+
+            for (page, previous) in undo.pages {
+                match previous {
+                    Some(buf) => self.dirty.insert(page, buf),
+                    None => self.dirty.remove(&page),
+                };
+            }
+
+            self.page_count = undo.page_count;
+            self.free_head = undo.free_head;
+
+            if touched {
+                lock(&self.cache).clear();
+            }
+        }
+    }
+
+    pub fn set_copy_on_write(&mut self, enabled: bool) {
+        self.copy_on_write = enabled;
+    }
+
+    fn is_shared(&self, page: u64) -> bool {
+        match &self.transaction {
+            Some(undo) => self.copy_on_write && !undo.fresh.contains(&page),
+            None => false,
+        }
+    }
+
+    // With copy-on-write on, a page written before the transaction began may
+    // still be read through an older root: updates go to a fresh page and the
+    // old one is only retired, to be freed once nothing can reach it.
+    pub fn relocate(&mut self, page: u64) -> Result<u64> {
+        if !self.is_shared(page) {
+            return Ok(page);
+        }
+
+        let relocated = self.allocate()?;
+
+        if let Some(undo) = &mut self.transaction {
+            undo.retired.push(page);
+        }
+
+        Ok(relocated)
+    }
+
+    pub fn retire(&mut self, page: u64) -> Result<()> {
+        match (self.is_shared(page), &mut self.transaction) {
+            (true, Some(undo)) => {
+                undo.retired.push(page);
+                Ok(())
+            }
+            _ => self.free(page),
+        }
+    }
+
+    pub fn retired(&mut self) -> Vec<u64> {
+        match &mut self.transaction {
+            Some(undo) => std::mem::take(&mut undo.retired),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn write_back(&self) -> bool {
+        self.write_back
+    }
+
+    pub fn set_write_back(&mut self, enabled: bool) -> Result<()> {
+        if !enabled {
+            self.flush()?;
+        }
+
+        self.write_back = enabled;
+        Ok(())
+    }
+
+    pub fn dirty_pages(&self) -> usize {
+        self.dirty.len()
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        if self.dirty.is_empty() {
+            return Ok(());
+        }
+
+        let mut pages: Vec<Frame> = self.dirty.drain().collect();
+        pages.sort_by_key(|(page, _)| *page);
+
+        wal::write(&self.wal, self.page_size, &pages)?;
+        let file = self.file.get_mut().unwrap_or_else(PoisonError::into_inner);
+        Pager::apply(file, &pages)?;
+        wal::remove(&self.wal)
+    }
+
+    pub fn set_cache_capacity(&mut self, capacity: Capacity) {
+        lock(&self.cache).resize(capacity);
+    }
+
+    pub fn cache_stats(&self) -> Stats {
+        lock(&self.cache).stats()
+    }
+
+    fn save_header(&mut self) -> Result<()> {
+        let mut header = vec![0; self.page_size];
+        header[0..8].copy_from_slice(MAGIC);
+        header[8..12].copy_from_slice(&(self.page_size as u32).to_le_bytes());
+        header[12..20].copy_from_slice(&self.page_count.to_le_bytes());
+        header[20..28].copy_from_slice(&self.free_head.to_le_bytes());
+        header[28..36].copy_from_slice(&self.generation.to_le_bytes());
+        self.write_page(0, &header)
+    }
+
+    fn read_page(&self, page: u64) -> Result<Vec<u8>> {
+        if let Some(buf) = self.dirty.get(&page) {
+            return Ok(buf.clone());
+        }
+
+        if page >= self.page_count {
+            return Err(Error::Corrupt(format!("page {} out of bounds", page)));
+        }
+
+        let mut buf = vec![0; self.page_size];
+        let mut file = lock(&self.file);
+        file.seek(SeekFrom::Start(page * self.page_size as u64))?;
+        file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn write_page(&mut self, page: u64, buf: &[u8]) -> Result<()> {
+        if let Some(undo) = &mut self.transaction {
+            undo.pages
+                .entry(page)
+                .or_insert_with(|| self.dirty.get(&page).cloned());
+        }
+
+        match self.write_back || self.transaction.is_some() {
+            true => {
+                self.dirty.insert(page, buf.to_vec());
+                Ok(())
+            }
+            false => {
+                let file = self.file.get_mut().unwrap_or_else(PoisonError::into_inner);
+                file.seek(SeekFrom::Start(page * self.page_size as u64))?;
+                file.write_all(buf)?;
+                Ok(())
+            }
+        }
+    }
+
+    fn next_page(buf: &[u8]) -> u64 {
+        u64::from_le_bytes(buf[0..8].try_into().unwrap())
+    }
+
+    // A chain can't be longer than the file, so one that is must loop.
+    fn check_hops(&self, hops: u64, page: u64) -> Result<()> {
+        match hops > self.page_count {
+            true => Err(Error::Corrupt(format!("page chain from {} loops", page))),
+            false => Ok(()),
+        }
+    }
+
+    fn chain(&self, page: u64) -> Result<Vec<u64>> {
+        let mut pages = vec![page];
+        let mut next = Pager::next_page(&self.read_page(page)?);
+
+        while next != 0 {
+            pages.push(next);
+            self.check_hops(pages.len() as u64, page)?;
+            next = Pager::next_page(&self.read_page(next)?);
+        }
+
+        Ok(pages)
+    }
+
+    pub fn allocate(&mut self) -> Result<u64> {
+        let page = match self.free_head {
+            0 => {
+                self.page_count += 1;
+                self.page_count - 1
+            }
+            page => {
+                self.free_head = Pager::next_page(&self.read_page(page)?);
+                page
+            }
+        };
+
+        if let Some(undo) = &mut self.transaction {
+            undo.fresh.insert(page);
+        }
+
+        self.write_page(page, &vec![0; self.page_size])?;
+        self.save_header()?;
+        Ok(page)
+    }
+
+    fn release(&mut self, page: u64) -> Result<()> {
+        let mut buf = vec![0; self.page_size];
+        buf[0..8].copy_from_slice(&self.free_head.to_le_bytes());
+        self.write_page(page, &buf)?;
+
+        self.free_head = page;
+        self.save_header()
+    }
+
+    pub fn read(&self, page: u64) -> Result<Vec<u8>> {
+        if let Some(data) = lock(&self.cache).get(page) {
+            return Ok(data);
+        }
+
+        let mut data = Vec::new();
+        let mut next = page;
+        let mut hops = 0;
+
+        while next != 0 {
+            hops += 1;
+            self.check_hops(hops, page)?;
+
+            let buf = self.read_page(next)?;
+            let len = u32::from_le_bytes(buf[8..12].try_into().unwrap()) as usize;
+
+            if PAGE_HEADER + len > buf.len() {
+                return Err(Error::Corrupt(format!("page {} overflows", next)));
+            }
+
+            data.extend_from_slice(&buf[PAGE_HEADER..PAGE_HEADER + len]);
+            next = Pager::next_page(&buf);
+        }
+
+        lock(&self.cache).put(page, data.clone());
+        Ok(data)
+    }
+
+    pub fn write(&mut self, page: u64, data: &[u8]) -> Result<()> {
+        let chunks: Vec<&[u8]> = match data.is_empty() {
+            true => vec![data],
+            false => data.chunks(self.page_size - PAGE_HEADER).collect(),
+        };
+
+        let mut pages = self.chain(page)?;
+
+        while pages.len() < chunks.len() {
+            pages.push(self.allocate()?);
+        }
+
+        for extra in pages.split_off(chunks.len()) {
+            self.release(extra)?;
+        }
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            let next = pages.get(i + 1).copied().unwrap_or(0);
+
+            let mut buf = vec![0; self.page_size];
+            buf[0..8].copy_from_slice(&next.to_le_bytes());
+            buf[8..12].copy_from_slice(&(chunk.len() as u32).to_le_bytes());
+            buf[PAGE_HEADER..PAGE_HEADER + chunk.len()].copy_from_slice(chunk);
+            self.write_page(pages[i], &buf)?;
+        }
+
+        lock(&self.cache).put(page, data.to_vec());
+        Ok(())
+    }
+
+    pub fn free(&mut self, page: u64) -> Result<()> {
+        lock(&self.cache).remove(page);
+
+        for page in self.chain(page)? {
+            self.release(page)?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub fn used_pages(&self) -> Result<u64> {
+        let mut free = 0;
+        let mut next = self.free_head;
+
+        while next != 0 {
+            free += 1;
+            self.check_hops(free, self.free_head)?;
+            next = Pager::next_page(&self.read_page(next)?);
+        }
+
+        Ok(self.page_count - free)
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        self.rollback();
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn create() {
+        let filename = "pager_test_create.idx";
+        let pager = Pager::create(filename).unwrap();
+
+        assert_eq!(pager.page_count, 2);
+        assert_eq!(pager.read(META_PAGE).unwrap(), Vec::<u8>::new());
+        assert_eq!(fs::metadata(filename).unwrap().len(), 2 * PAGE_SIZE as u64);
+
+        fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn open() {
+        let filename = "pager_test_open.idx";
+        let mut pager = Pager::create(filename).unwrap();
+
+        let page = pager.allocate().unwrap();
+        pager.write(page, b"sample").unwrap();
+
+        let pager = Pager::open(filename).unwrap();
+        assert_eq!(pager.page_count, 3);
+        assert_eq!(pager.read(page).unwrap(), b"sample");
+
+        fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn open_corrupt() {
+        let filename = "pager_test_open_corrupt.idx";
+        fs::write(filename, vec![0; PAGE_SIZE]).unwrap();
+
+        assert!(matches!(Pager::open(filename), Err(Error::Corrupt(_))));
+        assert!(matches!(
+            Pager::open("pager_test_missing.idx"),
+            Err(Error::Io(_))
+        ));
+
+        fs::remove_file(filename).unwrap();
+
+        let mut pager = Pager::create(filename).unwrap();
+        let page = pager.allocate().unwrap();
+        pager.write(page, b"sample").unwrap();
+        drop(pager);
+
+        let valid = fs::read(filename).unwrap();
+
+        for (offset, bytes) in [
+            (8, 0u32.to_le_bytes().to_vec()),
+            (8, 4u32.to_le_bytes().to_vec()),
+            (12, 1000u64.to_le_bytes().to_vec()),
+        ] {
+            let mut corrupt = valid.clone();
+            corrupt[offset..offset + bytes.len()].copy_from_slice(&bytes);
+            fs::write(filename, corrupt).unwrap();
+
+            assert!(matches!(Pager::open(filename), Err(Error::Corrupt(_))));
+        }
+
+        let mut looped = valid.clone();
+        let start = page as usize * PAGE_SIZE;
+        looped[start..start + 8].copy_from_slice(&page.to_le_bytes());
+        fs::write(filename, looped).unwrap();
+
+        let pager = Pager::open(filename).unwrap();
+        assert!(matches!(pager.read(page), Err(Error::Corrupt(_))));
+
+        fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn write() {
+        let filename = "pager_test_write.idx";
+        let mut pager = Pager::create(filename).unwrap();
+
+        let page = pager.allocate().unwrap();
+        let data: Vec<u8> = (0..3 * PAGE_SIZE).map(|i| (i % 251) as u8).collect();
+
+        pager.write(page, &data).unwrap();
+        assert_eq!(pager.read(page).unwrap(), data);
+        assert_eq!(pager.used_pages().unwrap(), 6);
+
+        pager.write(page, &data[0..10]).unwrap();
+        assert_eq!(pager.read(page).unwrap(), &data[0..10]);
+        assert_eq!(pager.used_pages().unwrap(), 3);
+
+        fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn free() {
+        let filename = "pager_test_free.idx";
+        let mut pager = Pager::create(filename).unwrap();
+
+        let page = pager.allocate().unwrap();
+        pager.write(page, &vec![1; 2 * PAGE_SIZE]).unwrap();
+        assert_eq!(pager.used_pages().unwrap(), 5);
+
+        pager.free(page).unwrap();
+        assert_eq!(pager.used_pages().unwrap(), 2);
+
+        let reused = pager.allocate().unwrap();
+        assert!(reused < pager.page_count);
+        assert_eq!(pager.page_count, 5);
+        assert_eq!(pager.read(reused).unwrap(), Vec::<u8>::new());
+
+        fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn cache() {
+        let filename = "pager_test_cache.idx";
+        let mut pager = Pager::create(filename).unwrap();
+
+        let page = pager.allocate().unwrap();
+        pager.write(page, b"sample").unwrap();
+
+        assert_eq!(pager.read(page).unwrap(), b"sample");
+        assert_eq!(pager.read(page).unwrap(), b"sample");
+        assert_eq!(pager.cache_stats().hits, 2);
+        assert_eq!(pager.cache_stats().misses, 0);
+
+        pager.set_cache_capacity(Capacity::Nodes(0));
+
+        assert_eq!(pager.read(page).unwrap(), b"sample");
+        assert_eq!(pager.cache_stats().misses, 1);
+        assert_eq!(pager.cache_stats().entries, 0);
+
+        fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn flush() {
+        let filename = "pager_test_flush.idx";
+        let mut pager = Pager::create(filename).unwrap();
+        pager.set_write_back(true).unwrap();
+
+        let page = pager.allocate().unwrap();
+        pager.write(page, &vec![1; 2 * PAGE_SIZE]).unwrap();
+
+        assert_eq!(pager.dirty_pages(), 4);
+        assert_eq!(Pager::open(filename).unwrap().page_count, 2);
+
+        pager.flush().unwrap();
+
+        assert_eq!(pager.dirty_pages(), 0);
+        assert_eq!(
+            Pager::open(filename).unwrap().read(page).unwrap(),
+            vec![1; 2 * PAGE_SIZE]
+        );
+
+        pager.write(page, b"sample").unwrap();
+        drop(pager);
+
+        assert_eq!(
+            Pager::open(filename).unwrap().read(page).unwrap(),
+            b"sample"
+        );
+
+        fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn transaction() {
+        let filename = "pager_test_transaction.idx";
+        let mut pager = Pager::create(filename).unwrap();
+
+        let page = pager.allocate().unwrap();
+        pager.write(page, b"sample").unwrap();
+
+        pager.begin();
+        pager.write(page, b"changed").unwrap();
+        let extra = pager.allocate().unwrap();
+
+        assert_eq!(pager.read(page).unwrap(), b"changed");
+        assert_eq!(
+            Pager::open(filename).unwrap().read(page).unwrap(),
+            b"sample"
+        );
+
+        pager.rollback();
+
+        assert_eq!(pager.read(page).unwrap(), b"sample");
+        assert_eq!(pager.dirty_pages(), 0);
+        assert_eq!(pager.allocate().unwrap(), extra);
+
+        pager.begin();
+        pager.write(page, b"changed").unwrap();
+        pager.commit().unwrap();
+
+        assert_eq!(pager.dirty_pages(), 0);
+        assert_eq!(
+            Pager::open(filename).unwrap().read(page).unwrap(),
+            b"changed"
+        );
+
+        pager.begin();
+        pager.write(page, b"lost").unwrap();
+        drop(pager);
+
+        assert_eq!(
+            Pager::open(filename).unwrap().read(page).unwrap(),
+            b"changed"
+        );
+
+        fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn recover() {
+        let filename = "pager_test_recover.idx";
+        let mut pager = Pager::create(filename).unwrap();
+        pager.set_write_back(true).unwrap();
+
+        let page = pager.allocate().unwrap();
+        pager.write(page, b"sample").unwrap();
+
+        let mut pages: Vec<Frame> = pager.dirty.drain().collect();
+        pages.sort_by_key(|(page, _)| *page);
+        wal::write(&pager.wal, PAGE_SIZE, &pages).unwrap();
+        drop(pager);
+
+        let pager = Pager::open(filename).unwrap();
+        assert_eq!(pager.read(page).unwrap(), b"sample");
+        assert!(fs::metadata(wal::filename(filename)).is_err());
+
+        let mut pager = pager;
+        pager.set_write_back(true).unwrap();
+        pager.write(page, b"changed").unwrap();
+
+        let pages: Vec<Frame> = pager.dirty.drain().collect();
+        wal::write(&pager.wal, PAGE_SIZE, &pages).unwrap();
+        drop(pager);
+
+        let wal = wal::filename(filename);
+        let buf = fs::read(&wal).unwrap();
+        fs::write(&wal, &buf[..buf.len() - 8]).unwrap();
+
+        let pager = Pager::open(filename).unwrap();
+        assert_eq!(pager.read(page).unwrap(), b"sample");
+        assert!(fs::metadata(&wal).is_err());
+
+        fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn refresh() {
+        let filename = "pager_test_refresh.idx";
+        let mut pager = Pager::create(filename).unwrap();
+        let page = pager.allocate().unwrap();
+        pager.write(page, b"sample").unwrap();
+
+        let mut other = Pager::open(filename).unwrap();
+        assert_eq!(other.read(page).unwrap(), b"sample");
+
+        pager.begin();
+        pager.write(page, b"changed").unwrap();
+        pager.allocate().unwrap();
+        pager.commit().unwrap();
+
+        assert!(!pager.refresh().unwrap());
+        assert!(other.refresh().unwrap());
+        assert_eq!(other.generation(), pager.generation());
+        assert_eq!(other.page_count, pager.page_count);
+        assert_eq!(other.read(page).unwrap(), b"changed");
+
+        fs::remove_file(filename).unwrap();
+    }
+
+    #[test]
+    fn copy_on_write() {
+        let filename = "pager_test_copy_on_write.idx";
+        let mut pager = Pager::create(filename).unwrap();
+        let page = pager.allocate().unwrap();
+        pager.write(page, b"sample").unwrap();
+
+        pager.set_copy_on_write(true);
+        pager.begin();
+
+        let relocated = pager.relocate(page).unwrap();
+        assert_ne!(relocated, page);
+        assert_eq!(pager.relocate(relocated).unwrap(), relocated);
+
+        pager.write(relocated, b"changed").unwrap();
+        pager.retire(relocated).unwrap();
+        assert_eq!(pager.retired(), [page]);
+        pager.commit().unwrap();
+
+        assert_eq!(pager.read(page).unwrap(), b"sample");
+        assert_eq!(pager.used_pages().unwrap(), 3);
+
+        pager.begin();
+        pager.retire(page).unwrap();
+        pager.rollback();
+        assert_eq!(pager.read(page).unwrap(), b"sample");
+
+        pager.set_copy_on_write(false);
+        pager.begin();
+        assert_eq!(pager.relocate(page).unwrap(), page);
+        pager.retire(page).unwrap();
+        assert!(pager.retired().is_empty());
+        pager.commit().unwrap();
+        assert_eq!(pager.used_pages().unwrap(), 2);
+
+        fs::remove_file(filename).unwrap();
+    }
+}
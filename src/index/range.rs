@@ -0,0 +1,321 @@
+use crate::error::Result;
+use crate::index::key::Key;
+use crate::index::node::Node;
+use crate::index::pager::Pager;
+use crate::index::value::Value;
+use std::ops::{Bound, RangeBounds};
+
+pub struct Range<'a> {
+    pager: &'a Pager,
+    root: Option<Node>,
+    stack: Vec<(Node, usize)>,
+    start: Bound<Vec<Value>>,
+    end: Bound<Vec<Value>>,
+    reverse: bool,
+}
+
+impl<'a> Range<'a> {
+    pub(crate) fn bounded(
+        pager: &'a Pager,
+        root: &Node,
+        start: Bound<Vec<Value>>,
+        end: Bound<Vec<Value>>,
+        reverse: bool,
+    ) -> Range<'a> {
+        Range {
+            pager,
+            root: Some(root.clone()),
+            stack: Vec::new(),
+            start,
+            end,
+            reverse,
+        }
+    }
+
+    pub(crate) fn create<'b, R: RangeBounds<&'b str>>(
+        pager: &'a Pager,
+        root: &Node,
+        bounds: R,
+    ) -> Range<'a> {
+        Range::bounded(
+            pager,
+            root,
+            bounds.start_bound().map(|value| vec![Value::from(*value)]),
+            bounds.end_bound().map(|value| vec![Value::from(*value)]),
+            false,
+        )
+    }
+
+    pub(crate) fn create_rev<'b, R: RangeBounds<&'b str>>(
+        pager: &'a Pager,
+        root: &Node,
+        bounds: R,
+    ) -> Range<'a> {
+        Range::bounded(
+            pager,
+            root,
+            bounds.start_bound().map(|value| vec![Value::from(*value)]),
+            bounds.end_bound().map(|value| vec![Value::from(*value)]),
+            true,
+        )
+    }
+
+    pub(crate) fn create_prefix(pager: &'a Pager, root: &Node, prefix: Vec<Value>) -> Range<'a> {
+        Range::bounded(
+            pager,
+            root,
+            Bound::Included(prefix.clone()),
+            Bound::Included(prefix),
+            false,
+        )
+    }
+
+    fn descend(&mut self, node: Node) -> Result<()> {
+        let idx = match self.reverse {
+            false => node.keys.partition_point(|key| !self.after_start(key)),
+            true => node.keys.partition_point(|key| self.before_end(key)),
+        };
+
+        let child = match node.leaf {
+            true => None,
+            false => Some(Node::load(self.pager, node.children[idx])?),
+        };
+
+        self.stack.push((node, idx));
+
+        match child {
+            Some(child) => self.descend(child),
+            None => Ok(()),
+        }
+    }
+
+    fn after_start(&self, key: &Key) -> bool {
+        match &self.start {
+            Bound::Included(value) => key.cmp_prefix(value).is_ge(),
+            Bound::Excluded(value) => key.cmp_prefix(value).is_gt(),
+            Bound::Unbounded => true,
+        }
+    }
+
+    fn before_end(&self, key: &Key) -> bool {
+        match &self.end {
+            Bound::Included(value) => key.cmp_prefix(value).is_le(),
+            Bound::Excluded(value) => key.cmp_prefix(value).is_lt(),
+            Bound::Unbounded => true,
+        }
+    }
+}
+
+impl Iterator for Range<'_> {
+    type Item = Result<Key>;
+
+    fn next(&mut self) -> Option<Result<Key>> {
+        if let Some(root) = self.root.take() {
+            if let Err(error) = self.descend(root) {
+                self.stack.clear();
+                return Some(Err(error));
+            }
+        }
+
+        loop {
+            let (node, idx) = self.stack.last_mut()?;
+
+            let exhausted = match self.reverse {
+                false => *idx == node.keys.len(),
+                true => *idx == 0,
+            };
+
+            if exhausted {
+                self.stack.pop();
+                continue;
+            }
+
+            let (key, child) = match self.reverse {
+                false => {
+                    *idx += 1;
+                    (node.keys[*idx - 1].clone(), *idx)
+                }
+                true => {
+                    *idx -= 1;
+                    (node.keys[*idx].clone(), *idx)
+                }
+            };
+
+            if !node.leaf {
+                let page = node.children[child];
+
+                if let Err(error) =
+                    Node::load(self.pager, page).and_then(|child| self.descend(child))
+                {
+                    self.stack.clear();
+                    return Some(Err(error));
+                }
+            }
+
+            let in_bounds = match self.reverse {
+                false => self.before_end(&key),
+                true => self.after_start(&key),
+            };
+
+            if !in_bounds {
+                self.stack.clear();
+                return None;
+            }
+
+            return Some(Ok(key));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    const _PLACEHOLDER: (u64, u64) = (0, 0);
+
+    fn _create_key(value: &str) -> Key {
+        Key::create(value, _PLACEHOLDER)
+    }
+
+    fn _values(range: Range) -> Vec<String> {
+        range
+            .map(|key| {
+                let key = key.unwrap();
+                let value: Vec<String> = key.value.iter().map(|value| value.to_string()).collect();
+                value.join(",")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn create() {
+        let order = 2;
+        let path = "range_test_create.idx";
+        let mut pager = Pager::create(path).unwrap();
+        let mut node = Node::empty(order, true, &mut pager).unwrap();
+
+        ["B", "D", "F", "H", "J", "L", "N"].iter().for_each(|s| {
+            if node.is_full(order) {
+                let mut father = Node::empty(order, false, &mut pager).unwrap();
+                father.children.push(node.page);
+                father.split(0, order, &mut pager).unwrap();
+                node = father;
+            }
+            node.insert(_create_key(s), order, &mut pager).unwrap();
+        });
+
+        assert_eq!(
+            _values(Range::create(&pager, &node, "D".."J")),
+            ["D", "F", "H"]
+        );
+        assert_eq!(
+            _values(Range::create(&pager, &node, "C"..="J")),
+            ["D", "F", "H", "J"]
+        );
+        assert_eq!(_values(Range::create(&pager, &node, "K"..)), ["L", "N"]);
+        assert_eq!(_values(Range::create(&pager, &node, ..="B")), ["B"]);
+        assert_eq!(
+            _values(Range::create(&pager, &node, "E".."F")),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            _values(Range::create(
+                &pager,
+                &node,
+                (Bound::Excluded("B"), Bound::Excluded("F"))
+            )),
+            ["D"]
+        );
+        assert_eq!(_values(Range::create(&pager, &node, ..)).len(), 7);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn create_rev() {
+        let order = 2;
+        let path = "range_test_create_rev.idx";
+        let mut pager = Pager::create(path).unwrap();
+        let mut node = Node::empty(order, true, &mut pager).unwrap();
+
+        ["B", "D", "F", "H", "J", "L", "N"].iter().for_each(|s| {
+            if node.is_full(order) {
+                let mut father = Node::empty(order, false, &mut pager).unwrap();
+                father.children.push(node.page);
+                father.split(0, order, &mut pager).unwrap();
+                node = father;
+            }
+            node.insert(_create_key(s), order, &mut pager).unwrap();
+        });
+
+        assert_eq!(
+            _values(Range::create_rev(&pager, &node, "D".."J")),
+            ["H", "F", "D"]
+        );
+        assert_eq!(
+            _values(Range::create_rev(&pager, &node, "C"..="J")),
+            ["J", "H", "F", "D"]
+        );
+        assert_eq!(_values(Range::create_rev(&pager, &node, "K"..)), ["N", "L"]);
+        assert_eq!(_values(Range::create_rev(&pager, &node, ..="B")), ["B"]);
+        assert_eq!(
+            _values(Range::create_rev(&pager, &node, ..)),
+            ["N", "L", "J", "H", "F", "D", "B"]
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn create_prefix() {
+        let order = 2;
+        let path = "range_test_create_prefix.idx";
+        let mut pager = Pager::create(path).unwrap();
+        let mut node = Node::empty(order, true, &mut pager).unwrap();
+
+        [
+            ("AR", 1),
+            ("BR", 10),
+            ("BR", 2),
+            ("BR", 1),
+            ("CL", 1),
+            ("PT", 1),
+            ("PT", 2),
+        ]
+        .into_iter()
+        .for_each(|(country, code)| {
+            if node.is_full(order) {
+                let mut father = Node::empty(order, false, &mut pager).unwrap();
+                father.children.push(node.page);
+                father.split(0, order, &mut pager).unwrap();
+                node = father;
+            }
+            let value = vec![Value::from(country), Value::UInt(code)];
+            node.insert(Key::composite(value, _PLACEHOLDER), order, &mut pager)
+                .unwrap();
+        });
+
+        assert_eq!(
+            _values(Range::create_prefix(&pager, &node, vec![Value::from("BR")])),
+            ["BR,1", "BR,2", "BR,10"]
+        );
+        assert_eq!(
+            _values(Range::create_prefix(
+                &pager,
+                &node,
+                vec![Value::from("PT"), Value::UInt(2)]
+            )),
+            ["PT,2"]
+        );
+        assert_eq!(
+            _values(Range::create_prefix(&pager, &node, vec![Value::from("ES")])),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            _values(Range::create_prefix(&pager, &node, Vec::new())).len(),
+            7
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,322 @@
+use crate::error::{Error, Result};
+use crate::index::encoding::{write_bytes, write_varint, Reader};
+use crate::index::key::Components;
+use crate::index::value::{Kind, ParseError, Value};
+use std::fmt;
+use std::ops::Bound;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Column {
+    Position(usize),
+    Name(String),
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Column::Position(position) => write!(f, "#{}", position),
+            Column::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Invalid {
+    Fail,
+    Skip,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Duplicates {
+    Fail,
+    KeepFirst,
+    KeepLast,
+    Report,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schema {
+    pub columns: Vec<Column>,
+    pub kinds: Vec<Kind>,
+    pub header: bool,
+    pub invalid: Invalid,
+    pub unique: Option<Duplicates>,
+}
+
+impl Default for Schema {
+    fn default() -> Schema {
+        Schema::position(0, false)
+    }
+}
+
+impl Schema {
+    pub fn position(position: usize, header: bool) -> Schema {
+        Schema::composite(vec![Column::Position(position)], header)
+    }
+
+    pub fn name(name: &str) -> Schema {
+        Schema::composite(vec![Column::Name(name.to_string())], true)
+    }
+
+    pub fn composite(columns: Vec<Column>, header: bool) -> Schema {
+        Schema {
+            kinds: vec![Kind::Str; columns.len()],
+            columns,
+            header,
+            invalid: Invalid::Fail,
+            unique: None,
+        }
+    }
+
+    pub fn typed(mut self, kinds: Vec<Kind>) -> Schema {
+        self.kinds = kinds;
+        self
+    }
+
+    pub fn skip_invalid(mut self) -> Schema {
+        self.invalid = Invalid::Skip;
+        self
+    }
+
+    pub fn unique(mut self, duplicates: Duplicates) -> Schema {
+        self.unique = Some(duplicates);
+        self
+    }
+
+    // The builders above can't fail, so a schema is checked once, when an
+    // index is created with it.
+    pub fn validate(&self) -> Result<()> {
+        if self.columns.is_empty() {
+            return Err(Error::InvalidSchema(String::from(
+                "schema needs at least one column",
+            )));
+        }
+
+        if self.kinds.len() != self.columns.len() {
+            return Err(Error::InvalidSchema(String::from(
+                "schema needs one type per column",
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn parse(&self, components: &[String]) -> std::result::Result<Vec<Value>, ParseError> {
+        components
+            .iter()
+            .zip(self.kinds.iter().chain(std::iter::repeat(&Kind::Str)))
+            .map(|(component, kind)| kind.parse(component))
+            .collect()
+    }
+
+    pub fn parse_bound(&self, bound: Bound<&&str>) -> Result<Bound<Vec<Value>>> {
+        Ok(match bound {
+            Bound::Included(value) => Bound::Included(self.parse(&value.components())?),
+            Bound::Excluded(value) => Bound::Excluded(self.parse(&value.components())?),
+            Bound::Unbounded => Bound::Unbounded,
+        })
+    }
+
+    pub fn resolve(&self, header: Option<&[String]>) -> Result<Vec<usize>> {
+        self.columns
+            .iter()
+            .map(|column| match column {
+                Column::Position(position) => Ok(*position),
+                Column::Name(name) => header
+                    .and_then(|header| header.iter().position(|field| field == name))
+                    .ok_or_else(|| Error::ColumnNotFound(name.clone())),
+            })
+            .collect()
+    }
+
+    pub(crate) fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(self.header as u8);
+        write_varint(buf, self.columns.len() as u64);
+
+        for column in &self.columns {
+            match column {
+                Column::Position(position) => {
+                    buf.push(0);
+                    write_varint(buf, *position as u64);
+                }
+                Column::Name(name) => {
+                    buf.push(1);
+                    write_bytes(buf, name.as_bytes());
+                }
+            }
+        }
+
+        self.kinds.iter().for_each(|kind| kind.encode(buf));
+        buf.push(self.invalid as u8);
+        buf.push(self.unique.map_or(0, |duplicates| duplicates as u8 + 1));
+    }
+
+    pub(crate) fn decode(reader: &mut Reader) -> Result<Schema> {
+        let header = match reader.read_u8()? {
+            0 => false,
+            1 => true,
+            tag => return Err(Error::Corrupt(format!("unknown header flag {}", tag))),
+        };
+
+        let columns: Vec<Column> = (0..reader.read_varint()?)
+            .map(|_| match reader.read_u8()? {
+                0 => Ok(Column::Position(reader.read_varint()? as usize)),
+                1 => Ok(Column::Name(reader.read_string()?)),
+                tag => Err(Error::Corrupt(format!("unknown column tag {}", tag))),
+            })
+            .collect::<Result<_>>()?;
+
+        if columns.is_empty() {
+            return Err(Error::Corrupt(String::from("schema without columns")));
+        }
+
+        let kinds = columns
+            .iter()
+            .map(|_| Kind::decode(reader))
+            .collect::<Result<_>>()?;
+
+        let invalid = match reader.read_u8()? {
+            0 => Invalid::Fail,
+            1 => Invalid::Skip,
+            tag => return Err(Error::Corrupt(format!("unknown invalid policy {}", tag))),
+        };
+
+        let unique = match reader.read_u8()? {
+            0 => None,
+            1 => Some(Duplicates::Fail),
+            2 => Some(Duplicates::KeepFirst),
+            3 => Some(Duplicates::KeepLast),
+            4 => Some(Duplicates::Report),
+            tag => return Err(Error::Corrupt(format!("unknown duplicates policy {}", tag))),
+        };
+
+        Ok(Schema {
+            columns,
+            kinds,
+            header,
+            invalid,
+            unique,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve() {
+        let header = vec![String::from("id"), String::from("city")];
+
+        assert_eq!(Schema::default().resolve(None).unwrap(), [0]);
+        assert_eq!(
+            Schema::position(3, true).resolve(Some(&header)).unwrap(),
+            [3]
+        );
+        assert_eq!(Schema::name("city").resolve(Some(&header)).unwrap(), [1]);
+        assert_eq!(
+            Schema::name("country")
+                .resolve(Some(&header))
+                .unwrap_err()
+                .to_string(),
+            "key column country not found"
+        );
+        assert!(Schema::name("city").resolve(None).is_err());
+
+        let composite = Schema::composite(
+            vec![Column::Name(String::from("city")), Column::Position(0)],
+            true,
+        );
+        assert_eq!(composite.resolve(Some(&header)).unwrap(), [1, 0]);
+    }
+
+    #[test]
+    fn encode() {
+        for schema in [
+            Schema::default(),
+            Schema::position(7, true),
+            Schema::name("city"),
+            Schema::composite(
+                vec![Column::Name(String::from("city")), Column::Position(2)],
+                true,
+            ),
+            Schema::position(1, false)
+                .typed(vec![Kind::Decimal])
+                .skip_invalid(),
+            Schema::name("id").unique(Duplicates::KeepLast),
+            Schema::name("id").unique(Duplicates::Report),
+        ] {
+            let mut buf = Vec::new();
+            schema.encode(&mut buf);
+
+            assert_eq!(Schema::decode(&mut Reader::create(&buf)).unwrap(), schema);
+        }
+    }
+
+    #[test]
+    fn decode_corrupt() {
+        let mut valid = Vec::new();
+        Schema::name("id")
+            .unique(Duplicates::Report)
+            .encode(&mut valid);
+
+        // Header flag, column tag, invalid policy and duplicates policy.
+        for offset in [0, 2, valid.len() - 2, valid.len() - 1] {
+            let mut corrupt = valid.clone();
+            corrupt[offset] = 9;
+
+            assert!(matches!(
+                Schema::decode(&mut Reader::create(&corrupt)),
+                Err(Error::Corrupt(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn validate() {
+        assert!(Schema::default().validate().is_ok());
+        assert!(Schema::position(0, false)
+            .typed(vec![Kind::Int])
+            .validate()
+            .is_ok());
+
+        for schema in [
+            Schema::composite(Vec::new(), false),
+            Schema::position(0, false).typed(Vec::new()),
+            Schema::position(0, false).typed(vec![Kind::Int, Kind::Date]),
+        ] {
+            assert!(matches!(schema.validate(), Err(Error::InvalidSchema(_))));
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let schema = Schema::composite(vec![Column::Position(0), Column::Position(1)], false)
+            .typed(vec![Kind::Int, Kind::Date]);
+
+        let components = vec![String::from("20"), String::from("2024-01-01")];
+        assert_eq!(
+            schema.parse(&components),
+            Ok(vec![
+                Value::Int(20),
+                Kind::Date.parse("2024-01-01").unwrap()
+            ])
+        );
+        assert_eq!(schema.parse(&components[..1]), Ok(vec![Value::Int(20)]));
+        assert_eq!(
+            schema.parse(&[components.clone(), vec![String::from("x")]].concat()),
+            Ok(vec![
+                Value::Int(20),
+                Kind::Date.parse("2024-01-01").unwrap(),
+                Value::from("x")
+            ])
+        );
+        assert_eq!(
+            schema.parse(&[String::from("x")]),
+            Err(ParseError {
+                value: String::from("x"),
+                kind: Kind::Int
+            })
+        );
+    }
+}
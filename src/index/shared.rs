@@ -0,0 +1,126 @@
+use crate::error::{Error, Result};
+use crate::index::btree::BTree;
+use crate::index::key::{Components, Key};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+// Readers hold the read lock for as long as they use the tree, so a writer
+// can't change pages under an open range and every reader sees the state
+// left by the last completed update.
+#[derive(Clone)]
+pub struct SharedTree {
+    tree: Arc<RwLock<BTree>>,
+}
+
+impl SharedTree {
+    pub fn create(tree: BTree) -> SharedTree {
+        SharedTree {
+            tree: Arc::new(RwLock::new(tree)),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<SharedTree> {
+        Ok(SharedTree::create(BTree::load(path)?))
+    }
+
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, BTree>> {
+        self.tree.read().map_err(|_| Error::Poisoned)
+    }
+
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, BTree>> {
+        self.tree.write().map_err(|_| Error::Poisoned)
+    }
+
+    pub fn lookup<C: Components>(&self, value: C) -> Result<Option<Key>> {
+        self.read()?.lookup(value)
+    }
+
+    pub fn insert(&self, key: Key) -> Result<()> {
+        self.write()?.insert(key)
+    }
+
+    pub fn delete<C: Components>(&self, value: C) -> Result<Key> {
+        self.write()?.delete(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::snapshot::Snapshot;
+    use crate::index::value::Value;
+    use std::fs;
+    use std::thread;
+
+    fn _send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn send_sync() {
+        _send_sync::<BTree>();
+        _send_sync::<SharedTree>();
+        _send_sync::<Snapshot>();
+    }
+
+    #[test]
+    fn concurrent() {
+        let path = "shared_test_concurrent";
+        let shared = SharedTree::create(BTree::create(2, path).unwrap());
+        let values: Vec<String> = (0..200).map(|i| format!("{:03}", i)).collect();
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+
+                thread::spawn(move || {
+                    let mut seen = 0;
+
+                    while seen < 200 {
+                        let tree = shared.read().unwrap();
+                        let keys: Vec<Vec<Value>> =
+                            tree.iter().map(|key| key.unwrap().value).collect();
+
+                        assert!(keys.len() >= seen);
+                        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+                        seen = keys.len();
+                    }
+                })
+            })
+            .collect();
+
+        for (i, value) in values.iter().enumerate() {
+            shared.insert(Key::create(value, (i as u64, 0))).unwrap();
+        }
+
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        assert_eq!(
+            shared.lookup("123").unwrap().map(|key| key.position),
+            Some((123, 0))
+        );
+
+        drop(shared);
+        assert_eq!(BTree::load(path).unwrap().iter().count(), 200);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn poisoned() {
+        let path = "shared_test_poisoned";
+        let shared = SharedTree::create(BTree::create(2, path).unwrap());
+
+        let writer = shared.clone();
+        let _ = thread::spawn(move || {
+            let _tree = writer.write().unwrap();
+            panic!("writer failed");
+        })
+        .join();
+
+        assert!(matches!(shared.read(), Err(Error::Poisoned)));
+        assert!(matches!(shared.lookup("A"), Err(Error::Poisoned)));
+
+        drop(shared);
+        fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -0,0 +1,155 @@
+use crate::error::Result;
+use crate::index::btree::BTree;
+use crate::index::key::{Components, Key};
+use crate::index::lock::Lock;
+use crate::index::node::Node;
+use crate::index::pager::Pager;
+use crate::index::range::Range;
+use crate::index::schema::Schema;
+use std::collections::BTreeMap;
+use std::ops::RangeBounds;
+use std::sync::{Arc, Mutex, PoisonError};
+
+// Generations still readable through a live snapshot, with how many snapshots
+// hold each one.
+#[derive(Clone, Default)]
+pub struct Pins {
+    generations: Arc<Mutex<BTreeMap<u64, usize>>>,
+}
+
+impl Pins {
+    fn update(&self, operation: impl FnOnce(&mut BTreeMap<u64, usize>)) {
+        let mut generations = self
+            .generations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        operation(&mut generations);
+    }
+
+    pub fn pin(&self, generation: u64) {
+        self.update(|generations| *generations.entry(generation).or_insert(0) += 1);
+    }
+
+    pub fn unpin(&self, generation: u64) {
+        self.update(|generations| {
+            if let Some(count) = generations.get_mut(&generation) {
+                *count -= 1;
+
+                if *count == 0 {
+                    generations.remove(&generation);
+                }
+            }
+        });
+    }
+
+    pub fn oldest(&self) -> Option<u64> {
+        let mut oldest = None;
+        self.update(|generations| oldest = generations.keys().next().copied());
+        oldest
+    }
+}
+
+// A read-only view of the tree as of one commit. It reads through its own
+// pager, so the tree it came from stays free to change; copy-on-write keeps
+// the pages it can reach from being rewritten until it's dropped.
+pub struct Snapshot {
+    root: Node,
+    schema: Schema,
+    generation: u64,
+    pager: Pager,
+    pins: Pins,
+    _lock: Arc<Mutex<Lock>>,
+}
+
+impl Snapshot {
+    pub(crate) fn create(
+        filename: &str,
+        root: Node,
+        schema: Schema,
+        pins: Pins,
+        lock: Arc<Mutex<Lock>>,
+    ) -> Result<Snapshot> {
+        let pager = Pager::open(filename)?;
+        let generation = pager.generation();
+        pins.pin(generation);
+
+        Ok(Snapshot {
+            root,
+            schema,
+            generation,
+            pager,
+            pins,
+            _lock: lock,
+        })
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn lookup<C: Components>(&self, value: C) -> Result<Option<Key>> {
+        match self.schema.parse(&value.components()) {
+            Ok(value) => BTree::search_tree(&self.pager, &self.root, &value),
+            Err(_) => Ok(None),
+        }
+    }
+
+    pub fn lookup_prefix<C: Components>(&self, prefix: C) -> Result<Range<'_>> {
+        let prefix = self.schema.parse(&prefix.components())?;
+        Ok(Range::create_prefix(&self.pager, &self.root, prefix))
+    }
+
+    pub fn range<'a, R: RangeBounds<&'a str>>(&self, bounds: R) -> Result<Range<'_>> {
+        Ok(Range::bounded(
+            &self.pager,
+            &self.root,
+            self.schema.parse_bound(bounds.start_bound())?,
+            self.schema.parse_bound(bounds.end_bound())?,
+            false,
+        ))
+    }
+
+    pub fn iter(&self) -> Range<'_> {
+        Range::create(&self.pager, &self.root, ..)
+    }
+
+    pub fn iter_rev(&self) -> Range<'_> {
+        Range::create_rev(&self.pager, &self.root, ..)
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        self.pins.unpin(self.generation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pins() {
+        let pins = Pins::default();
+        assert_eq!(pins.oldest(), None);
+
+        pins.pin(3);
+        pins.pin(1);
+        pins.pin(1);
+        assert_eq!(pins.oldest(), Some(1));
+
+        pins.unpin(1);
+        assert_eq!(pins.oldest(), Some(1));
+
+        pins.unpin(1);
+        assert_eq!(pins.oldest(), Some(3));
+
+        pins.unpin(3);
+        assert_eq!(pins.oldest(), None);
+    }
+}
@@ -0,0 +1,268 @@
+use crate::error::Result;
+use crate::index::encoding::Reader;
+use crate::index::key::Key;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter, ErrorKind};
+
+pub const RUN_SIZE: usize = 1_000_000;
+
+pub struct Sorter {
+    path: String,
+    run_size: usize,
+    buffer: Vec<Key>,
+    runs: Vec<String>,
+}
+
+struct Head {
+    key: Key,
+    run: usize,
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Head) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Head) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Head) -> Ordering {
+        other
+            .key
+            .value
+            .cmp(&self.key.value)
+            .then(other.run.cmp(&self.run))
+    }
+}
+
+pub struct Sorted {
+    memory: std::vec::IntoIter<Key>,
+    runs: Vec<BufReader<File>>,
+    heap: BinaryHeap<Head>,
+    pending: Option<Key>,
+    filenames: Vec<String>,
+}
+
+fn coalesce(keys: &mut Vec<Key>) {
+    keys.sort_by(|a, b| a.value.cmp(&b.value));
+    keys.dedup_by(|key, previous| {
+        let duplicate = key.value == previous.value;
+        if duplicate {
+            previous.merge(key);
+        }
+        duplicate
+    });
+}
+
+impl Sorter {
+    pub fn create(path: &str, run_size: usize) -> Sorter {
+        Sorter {
+            path: path.to_string(),
+            run_size,
+            buffer: Vec::new(),
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, key: Key) -> Result<()> {
+        self.buffer.push(key);
+
+        if self.buffer.len() >= self.run_size {
+            self.spill()?;
+        }
+
+        Ok(())
+    }
+
+    fn spill(&mut self) -> Result<()> {
+        let filename = format!("{}/sort-run-{}.tmp", self.path, self.runs.len());
+        let mut writer = BufWriter::new(File::create(&filename)?);
+
+        coalesce(&mut self.buffer);
+
+        for key in self.buffer.drain(..) {
+            let mut buf = Vec::new();
+            key.encode(&mut buf);
+            writer.write_all(&(buf.len() as u32).to_le_bytes())?;
+            writer.write_all(&buf)?;
+        }
+
+        writer.flush()?;
+        self.runs.push(filename);
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<Sorted> {
+        if self.runs.is_empty() {
+            coalesce(&mut self.buffer);
+
+            return Ok(Sorted {
+                memory: std::mem::take(&mut self.buffer).into_iter(),
+                runs: Vec::new(),
+                heap: BinaryHeap::new(),
+                pending: None,
+                filenames: Vec::new(),
+            });
+        }
+
+        if !self.buffer.is_empty() {
+            self.spill()?;
+        }
+
+        let mut sorted = Sorted {
+            memory: Vec::new().into_iter(),
+            runs: self
+                .runs
+                .iter()
+                .map(|filename| Ok(BufReader::new(File::open(filename)?)))
+                .collect::<Result<_>>()?,
+            heap: BinaryHeap::new(),
+            pending: None,
+            filenames: std::mem::take(&mut self.runs),
+        };
+
+        for run in 0..sorted.runs.len() {
+            sorted.advance(run)?;
+        }
+
+        Ok(sorted)
+    }
+}
+
+impl Sorted {
+    fn pop(&mut self) -> Result<Option<Key>> {
+        let head = match self.heap.pop() {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+
+        self.advance(head.run)?;
+        Ok(Some(head.key))
+    }
+
+    fn advance(&mut self, run: usize) -> Result<()> {
+        let mut len = [0; 4];
+
+        match self.runs[run].read_exact(&mut len) {
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?,
+        }
+
+        let mut buf = vec![0; u32::from_le_bytes(len) as usize];
+        self.runs[run].read_exact(&mut buf)?;
+
+        let key = Key::decode(&mut Reader::create(&buf))?;
+        self.heap.push(Head { key, run });
+        Ok(())
+    }
+}
+
+impl Iterator for Sorted {
+    type Item = Result<Key>;
+
+    fn next(&mut self) -> Option<Result<Key>> {
+        if self.runs.is_empty() {
+            return self.memory.next().map(Ok);
+        }
+
+        let mut key = match self.pending.take() {
+            Some(key) => key,
+            None => match self.pop() {
+                Ok(key) => key?,
+                Err(error) => return Some(Err(error)),
+            },
+        };
+
+        loop {
+            match self.pop() {
+                Ok(Some(next)) if next.value == key.value => key.merge(&next),
+                Ok(next) => {
+                    self.pending = next;
+                    return Some(Ok(key));
+                }
+                Err(error) => return Some(Err(error)),
+            }
+        }
+    }
+}
+
+// Runs left behind by a sort that never finished, e.g. when its input failed.
+impl Drop for Sorter {
+    fn drop(&mut self) {
+        for filename in &self.runs {
+            let _ = fs::remove_file(filename);
+        }
+    }
+}
+
+impl Drop for Sorted {
+    fn drop(&mut self) {
+        for filename in &self.filenames {
+            let _ = fs::remove_file(filename);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finish() {
+        let path = "sort_test_finish";
+        fs::create_dir(path).unwrap();
+
+        let mut sorter = Sorter::create(path, 10);
+        ["D", "B", "A", "C", "B"]
+            .iter()
+            .enumerate()
+            .for_each(|(i, s)| sorter.push(Key::create(s, (i as u64, 0))).unwrap());
+
+        let keys: Vec<Key> = sorter.finish().unwrap().map(Result::unwrap).collect();
+        assert_eq!(keys.len(), 4);
+        assert_eq!(keys[1].positions().collect::<Vec<_>>(), [(1, 0), (4, 0)]);
+
+        let values: Vec<String> = keys.iter().map(|key| key.value[0].to_string()).collect();
+        assert_eq!(values, ["A", "B", "C", "D"]);
+        assert_eq!(fs::read_dir(path).unwrap().count(), 0);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn spill() {
+        let path = "sort_test_spill";
+        fs::create_dir(path).unwrap();
+
+        let mut sorter = Sorter::create(path, 3);
+        ["H", "B", "G", "A", "F", "C", "E", "D", "B", "B"]
+            .iter()
+            .enumerate()
+            .for_each(|(i, s)| sorter.push(Key::create(s, (i as u64, 1))).unwrap());
+
+        assert_eq!(fs::read_dir(path).unwrap().count(), 3);
+
+        let keys: Vec<Key> = sorter.finish().unwrap().map(Result::unwrap).collect();
+        assert_eq!(keys.len(), 8);
+
+        let values: Vec<String> = keys.iter().map(|key| key.value[0].to_string()).collect();
+        assert_eq!(values, ["A", "B", "C", "D", "E", "F", "G", "H"]);
+        assert_eq!(keys[1].position, (1, 1));
+        assert_eq!(keys[1].postings, [(8, 1), (9, 1)]);
+
+        assert_eq!(fs::read_dir(path).unwrap().count(), 0);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+}
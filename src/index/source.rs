@@ -0,0 +1,243 @@
+use crate::error::{Error, Result};
+use crate::index::encoding::{write_bytes, write_varint, Checksum, Reader};
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::time::UNIX_EPOCH;
+
+pub const SAMPLE_SIZE: u64 = 64 * 1024;
+
+// `modified` is in nanoseconds since the epoch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Source {
+    pub path: String,
+    pub size: u64,
+    pub modified: u64,
+    pub hash: u64,
+    pub offset: u64,
+}
+
+impl Source {
+    pub fn read(path: &str) -> Result<Source> {
+        Source::read_prefix(path, fs::metadata(path)?.len())
+    }
+
+    // Describes only the first `size` bytes, for an index that stopped partway
+    // through the file.
+    pub fn read_prefix(path: &str, size: u64) -> Result<Source> {
+        let path = fs::canonicalize(path)?.to_string_lossy().into_owned();
+        let mut file = File::open(&path)?;
+        let metadata = file.metadata()?;
+        let size = size.min(metadata.len());
+
+        Ok(Source {
+            hash: Source::hash(&mut file, size)?,
+            path,
+            size,
+            modified: Source::modified(&metadata)?,
+            offset: 0,
+        })
+    }
+
+    fn modified(metadata: &fs::Metadata) -> Result<u64> {
+        Ok(metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64))
+    }
+
+    fn hash(file: &mut File, size: u64) -> Result<u64> {
+        let mut checksum = Checksum::create();
+        checksum.update(&size.to_le_bytes());
+
+        let head = size.min(SAMPLE_SIZE);
+        let tail = size.saturating_sub(head).min(SAMPLE_SIZE);
+
+        for (start, len) in [(0, head), (size - tail, tail)] {
+            let mut buf = vec![0; len as usize];
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(&mut buf)?;
+            checksum.update(&buf);
+        }
+
+        Ok(checksum.finish())
+    }
+
+    // Runs before every query, so it trusts an unchanged size and modification
+    // time and only samples the file again when the time moved, letting a file
+    // that was touched but not rewritten still pass.
+    pub fn check(&self) -> Result<()> {
+        match self.unchanged() {
+            Ok(true) => Ok(()),
+            Ok(false) | Err(Error::Io(_)) => Err(Error::Stale(self.path.clone())),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn unchanged(&self) -> Result<bool> {
+        let mut file = File::open(&self.path)?;
+        let metadata = file.metadata()?;
+
+        if metadata.len() != self.size {
+            return Ok(false);
+        }
+
+        Ok(Source::modified(&metadata)? == self.modified
+            || Source::hash(&mut file, self.size)? == self.hash)
+    }
+
+    pub fn check_prefix(&self, path: &str) -> Result<()> {
+        let changed = || Error::Changed(path.to_string());
+
+        let mut file = File::open(path)?;
+        let size = file.metadata()?.len();
+
+        if size < self.size || Source::hash(&mut file, self.size)? != self.hash {
+            return Err(changed());
+        }
+
+        if self.offset > 0 && size > self.offset {
+            let mut last = [0];
+            file.seek(SeekFrom::Start(self.offset - 1))?;
+            file.read_exact(&mut last)?;
+
+            if last[0] != b'\n' {
+                return Err(changed());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn encode(&self, buf: &mut Vec<u8>) {
+        write_bytes(buf, self.path.as_bytes());
+        write_varint(buf, self.size);
+        write_varint(buf, self.modified);
+        write_varint(buf, self.hash);
+        write_varint(buf, self.offset);
+    }
+
+    pub(crate) fn decode(reader: &mut Reader) -> Result<Source> {
+        Ok(Source {
+            path: reader.read_string()?,
+            size: reader.read_varint()?,
+            modified: reader.read_varint()?,
+            hash: reader.read_varint()?,
+            offset: reader.read_varint()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn read() {
+        let source = Source::read("resources/sample.csv").unwrap();
+
+        assert!(source.path.ends_with("resources/sample.csv"));
+        assert_eq!(
+            source.size,
+            fs::metadata("resources/sample.csv").unwrap().len()
+        );
+        assert!(source.modified > 0);
+        assert_eq!(source, Source::read("./resources/sample.csv").unwrap());
+        assert!(matches!(
+            Source::read("resources/missing.csv"),
+            Err(Error::Io(_))
+        ));
+    }
+
+    fn _touch(path: &str, modified: u64) {
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_nanos(modified))
+            .unwrap();
+    }
+
+    #[test]
+    fn check() {
+        let path = "source_test_check.csv";
+        fs::write(path, "id,name\n1,Ana\n").unwrap();
+
+        let source = Source::read(path).unwrap();
+        assert!(source.check().is_ok());
+
+        fs::write(path, "id,name\n1,Bia\n").unwrap();
+        _touch(path, source.modified + 1_000_000_000);
+        assert!(matches!(source.check(), Err(Error::Stale(_))));
+
+        fs::write(path, "id,name\n1,Ana\n2,Bia\n").unwrap();
+        assert!(matches!(source.check(), Err(Error::Stale(_))));
+
+        fs::remove_file(path).unwrap();
+        assert!(matches!(source.check(), Err(Error::Stale(_))));
+    }
+
+    #[test]
+    fn check_modified() {
+        let path = "source_test_check_modified.csv";
+        fs::write(path, "id,name\n1,Ana\n").unwrap();
+        let source = Source::read(path).unwrap();
+
+        // Touched without a change: the time moved but the sample matches.
+        _touch(path, source.modified + 1_000_000_000);
+        assert!(source.check().is_ok());
+
+        // Same size and time are trusted without reading the file.
+        fs::write(path, "id,name\n1,Bia\n").unwrap();
+        _touch(path, source.modified);
+        assert!(source.check().is_ok());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn check_prefix() {
+        let path = "source_test_check_prefix.csv";
+        fs::write(path, "id,name\n1,Ana\n").unwrap();
+
+        let source = Source {
+            offset: 14,
+            ..Source::read(path).unwrap()
+        };
+        assert!(source.check_prefix(path).is_ok());
+
+        fs::write(path, "id,name\n1,Ana\n2,Bia\n").unwrap();
+        assert!(source.check_prefix(path).is_ok());
+
+        fs::write(path, "id,name\n1,Bia\n2,Bia\n").unwrap();
+        assert!(matches!(source.check_prefix(path), Err(Error::Changed(_))));
+
+        fs::write(path, "id,name\n1,Ana").unwrap();
+        assert!(matches!(source.check_prefix(path), Err(Error::Changed(_))));
+
+        let source = Source {
+            offset: 13,
+            ..Source::read(path).unwrap()
+        };
+        assert!(source.check_prefix(path).is_ok());
+
+        fs::write(path, "id,name\n1,Ana2\n").unwrap();
+        assert!(matches!(source.check_prefix(path), Err(Error::Changed(_))));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn encode() {
+        let source = Source {
+            offset: 42,
+            ..Source::read("resources/header.csv").unwrap()
+        };
+
+        let mut buf = Vec::new();
+        source.encode(&mut buf);
+
+        assert_eq!(Source::decode(&mut Reader::create(&buf)).unwrap(), source);
+    }
+}
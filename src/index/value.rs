@@ -0,0 +1,577 @@
+use crate::error::{Error, Result};
+use crate::index::encoding::{write_bytes, write_signed, write_varint, Reader};
+use serde::{Serialize, Serializer};
+use std::cmp::Ordering;
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+const MICROS_PER_DAY: i64 = 86_400_000_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Int,
+    UInt,
+    Float,
+    Date,
+    Timestamp,
+    Decimal,
+    Str,
+}
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Date(i64),
+    Timestamp(i64),
+    Decimal {
+        negative: bool,
+        integer: String,
+        fraction: String,
+    },
+    Str(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub value: String,
+    pub kind: Kind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "can't parse {:?} as {}", self.value, self.kind)
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Kind::Int => "i64",
+            Kind::UInt => "u64",
+            Kind::Float => "f64",
+            Kind::Date => "date",
+            Kind::Timestamp => "timestamp",
+            Kind::Decimal => "decimal",
+            Kind::Str => "string",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Kind, String> {
+        match name {
+            "i64" | "int" => Ok(Kind::Int),
+            "u64" | "uint" => Ok(Kind::UInt),
+            "f64" | "float" => Ok(Kind::Float),
+            "date" => Ok(Kind::Date),
+            "timestamp" => Ok(Kind::Timestamp),
+            "decimal" => Ok(Kind::Decimal),
+            "string" | "str" => Ok(Kind::Str),
+            _ => Err(format!("unknown key type {}", name)),
+        }
+    }
+}
+
+fn digits(text: &str, len: usize) -> Option<i64> {
+    match text.len() == len && text.bytes().all(|byte| byte.is_ascii_digit()) {
+        true => text.parse().ok(),
+        false => None,
+    }
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+
+    (yoe + era * 400 + (month <= 2) as i64, month, day)
+}
+
+fn parse_date(text: &str) -> Option<i64> {
+    let mut parts = text.split('-');
+    let year = digits(parts.next()?, 4)?;
+    let month = digits(parts.next()?, 2)?;
+    let day = digits(parts.next()?, 2)?;
+
+    if parts.next().is_some() || !(1..=12).contains(&month) {
+        return None;
+    }
+
+    match (1..=days_in_month(year, month)).contains(&day) {
+        true => Some(days_from_civil(year, month, day)),
+        false => None,
+    }
+}
+
+fn parse_offset(text: &str) -> Option<i64> {
+    if text.is_empty() || text == "Z" {
+        return Some(0);
+    }
+
+    let (sign, text) = match text.as_bytes()[0] {
+        b'+' => (1, &text[1..]),
+        b'-' => (-1, &text[1..]),
+        _ => return None,
+    };
+
+    let (hours, minutes) = text.split_once(':')?;
+    let (hours, minutes) = (digits(hours, 2)?, digits(minutes, 2)?);
+
+    match hours < 24 && minutes < 60 {
+        true => Some(sign * (hours * 60 + minutes) * 60_000_000),
+        false => None,
+    }
+}
+
+fn parse_timestamp(text: &str) -> Option<i64> {
+    if text.len() < 19 || !text.is_char_boundary(10) || !text.is_char_boundary(19) {
+        return None;
+    }
+
+    let days = parse_date(&text[..10])?;
+
+    if !matches!(text.as_bytes()[10], b'T' | b' ') {
+        return None;
+    }
+
+    let mut clock = text[11..19].split(':');
+    let hours = digits(clock.next()?, 2)?;
+    let minutes = digits(clock.next()?, 2)?;
+    let seconds = digits(clock.next()?, 2)?;
+
+    if hours > 23 || minutes > 59 || seconds > 59 {
+        return None;
+    }
+
+    let rest = &text[19..];
+    let (fraction, offset) = match rest.strip_prefix('.') {
+        None => ("", rest),
+        Some(rest) => rest.split_at(
+            rest.find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len()),
+        ),
+    };
+
+    if rest.starts_with('.') && (fraction.is_empty() || fraction.len() > 9) {
+        return None;
+    }
+
+    let micros = format!("{:0<6}", fraction)[..6].parse::<i64>().unwrap();
+    let seconds = (hours * 60 + minutes) * 60 + seconds;
+
+    Some(days * MICROS_PER_DAY + seconds * 1_000_000 + micros - parse_offset(offset)?)
+}
+
+fn parse_decimal(text: &str) -> Option<Value> {
+    let (negative, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+
+    let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+
+    if integer.is_empty() && fraction.is_empty()
+        || !integer
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|byte| byte.is_ascii_digit())
+    {
+        return None;
+    }
+
+    let integer = integer.trim_start_matches('0').to_string();
+    let fraction = fraction.trim_end_matches('0').to_string();
+
+    Some(Value::Decimal {
+        negative: negative && !(integer.is_empty() && fraction.is_empty()),
+        integer,
+        fraction,
+    })
+}
+
+impl Kind {
+    pub fn parse(&self, text: &str) -> std::result::Result<Value, ParseError> {
+        let trimmed = text.trim();
+
+        let value = match self {
+            Kind::Int => trimmed.parse().ok().map(Value::Int),
+            Kind::UInt => trimmed.parse().ok().map(Value::UInt),
+            Kind::Float => trimmed.parse().ok().map(Value::Float),
+            Kind::Date => parse_date(trimmed).map(Value::Date),
+            Kind::Timestamp => parse_timestamp(trimmed).map(Value::Timestamp),
+            Kind::Decimal => parse_decimal(trimmed),
+            Kind::Str => Some(Value::Str(text.to_string())),
+        };
+
+        value.ok_or_else(|| ParseError {
+            value: text.to_string(),
+            kind: *self,
+        })
+    }
+
+    pub(crate) fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+
+    pub(crate) fn decode(reader: &mut Reader) -> Result<Kind> {
+        match reader.read_u8()? {
+            0 => Ok(Kind::Int),
+            1 => Ok(Kind::UInt),
+            2 => Ok(Kind::Float),
+            3 => Ok(Kind::Date),
+            4 => Ok(Kind::Timestamp),
+            5 => Ok(Kind::Decimal),
+            6 => Ok(Kind::Str),
+            tag => Err(Error::Corrupt(format!("unknown key type {}", tag))),
+        }
+    }
+}
+
+impl Value {
+    fn tag(&self) -> u8 {
+        match self {
+            Value::Int(_) => 0,
+            Value::UInt(_) => 1,
+            Value::Float(_) => 2,
+            Value::Date(_) => 3,
+            Value::Timestamp(_) => 4,
+            Value::Decimal { .. } => 5,
+            Value::Str(_) => 6,
+        }
+    }
+
+    pub(crate) fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(self.tag());
+
+        match self {
+            Value::Int(value) | Value::Date(value) | Value::Timestamp(value) => {
+                write_signed(buf, *value)
+            }
+            Value::UInt(value) => write_varint(buf, *value),
+            Value::Float(value) => buf.extend_from_slice(&value.to_bits().to_le_bytes()),
+            Value::Decimal {
+                negative,
+                integer,
+                fraction,
+            } => {
+                buf.push(*negative as u8);
+                write_bytes(buf, integer.as_bytes());
+                write_bytes(buf, fraction.as_bytes());
+            }
+            Value::Str(value) => write_bytes(buf, value.as_bytes()),
+        }
+    }
+
+    pub(crate) fn decode(reader: &mut Reader) -> Result<Value> {
+        match reader.read_u8()? {
+            0 => Ok(Value::Int(reader.read_signed()?)),
+            1 => Ok(Value::UInt(reader.read_varint()?)),
+            2 => {
+                let mut bits = [0; 8];
+                for byte in bits.iter_mut() {
+                    *byte = reader.read_u8()?;
+                }
+                Ok(Value::Float(f64::from_bits(u64::from_le_bytes(bits))))
+            }
+            3 => Ok(Value::Date(reader.read_signed()?)),
+            4 => Ok(Value::Timestamp(reader.read_signed()?)),
+            5 => Ok(Value::Decimal {
+                negative: reader.read_u8()? == 1,
+                integer: reader.read_string()?,
+                fraction: reader.read_string()?,
+            }),
+            6 => Ok(Value::Str(reader.read_string()?)),
+            tag => Err(Error::Corrupt(format!("unknown value tag {}", tag))),
+        }
+    }
+}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b))
+            | (Value::Date(a), Value::Date(b))
+            | (Value::Timestamp(a), Value::Timestamp(b)) => a.cmp(b),
+            (Value::UInt(a), Value::UInt(b)) => a.cmp(b),
+            (Value::Float(a), Value::Float(b)) => a.total_cmp(b),
+            (
+                Value::Decimal {
+                    negative: a_negative,
+                    integer: a_integer,
+                    fraction: a_fraction,
+                },
+                Value::Decimal {
+                    negative: b_negative,
+                    integer: b_integer,
+                    fraction: b_fraction,
+                },
+            ) => {
+                let magnitude = a_integer
+                    .len()
+                    .cmp(&b_integer.len())
+                    .then_with(|| a_integer.cmp(b_integer))
+                    .then_with(|| a_fraction.cmp(b_fraction));
+
+                match (a_negative, b_negative) {
+                    (false, false) => magnitude,
+                    (true, true) => magnitude.reverse(),
+                    (a_negative, _) => b_negative.cmp(a_negative),
+                }
+            }
+            (Value::Str(a), Value::Str(b)) => a.cmp(b),
+            (a, b) => a.tag().cmp(&b.tag()),
+        }
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+impl PartialEq<&str> for Value {
+    fn eq(&self, other: &&str) -> bool {
+        matches!(self, Value::Str(value) if value == other)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Value {
+        Value::Str(value.to_string())
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::UInt(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::Date(days) => {
+                let (year, month, day) = civil_from_days(*days);
+                write!(f, "{:04}-{:02}-{:02}", year, month, day)
+            }
+            Value::Timestamp(micros) => {
+                let (year, month, day) = civil_from_days(micros.div_euclid(MICROS_PER_DAY));
+                let micros = micros.rem_euclid(MICROS_PER_DAY);
+                let seconds = micros / 1_000_000;
+
+                write!(
+                    f,
+                    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+                    year,
+                    month,
+                    day,
+                    seconds / 3600,
+                    seconds / 60 % 60,
+                    seconds % 60
+                )?;
+
+                match micros % 1_000_000 {
+                    0 => write!(f, "Z"),
+                    fraction => write!(f, ".{:06}Z", fraction),
+                }
+            }
+            Value::Decimal {
+                negative,
+                integer,
+                fraction,
+            } => {
+                let sign = if *negative { "-" } else { "" };
+                let integer = if integer.is_empty() { "0" } else { integer };
+
+                match fraction.is_empty() {
+                    true => write!(f, "{}{}", sign, integer),
+                    false => write!(f, "{}{}.{}", sign, integer, fraction),
+                }
+            }
+            Value::Str(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            Value::Int(value) => serializer.serialize_i64(*value),
+            Value::UInt(value) => serializer.serialize_u64(*value),
+            Value::Float(value) => serializer.serialize_f64(*value),
+            value => serializer.serialize_str(&value.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn _parse(kind: Kind, values: &[&str]) -> Vec<Value> {
+        values
+            .iter()
+            .map(|value| kind.parse(value).unwrap())
+            .collect()
+    }
+
+    fn _sorted(kind: Kind, values: &[&str]) -> Vec<String> {
+        let mut parsed = _parse(kind, values);
+        parsed.sort();
+        parsed.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Kind::Int.parse(" -42 ").unwrap(), Value::Int(-42));
+        assert_eq!(Kind::UInt.parse("42").unwrap(), Value::UInt(42));
+        assert_eq!(Kind::Float.parse("1e3").unwrap(), Value::Float(1000.0));
+        assert_eq!(Kind::Date.parse("1970-01-02").unwrap(), Value::Date(1));
+        assert_eq!(Kind::Date.parse("1969-12-31").unwrap(), Value::Date(-1));
+        assert_eq!(
+            Kind::Timestamp.parse("1970-01-01T01:00:00+01:00").unwrap(),
+            Value::Timestamp(0)
+        );
+        assert_eq!(
+            Kind::Timestamp.parse("1970-01-01 00:00:01.5").unwrap(),
+            Value::Timestamp(1_500_000)
+        );
+        assert_eq!(Kind::Str.parse(" a ").unwrap(), " a ");
+
+        for (kind, text) in [
+            (Kind::Int, "1.5"),
+            (Kind::UInt, "-1"),
+            (Kind::Float, "abc"),
+            (Kind::Date, "2023-02-29"),
+            (Kind::Date, "2023-1-01"),
+            (Kind::Timestamp, "2023-01-01T24:00:00"),
+            (Kind::Timestamp, "2023-01-01T00:00:00."),
+            (Kind::Timestamp, "2024-01-01é00:00:00"),
+            (Kind::Timestamp, "2024-01-01T00:00:00é"),
+            (Kind::Decimal, "1.2.3"),
+            (Kind::Decimal, "."),
+        ] {
+            assert_eq!(
+                kind.parse(text),
+                Err(ParseError {
+                    value: text.to_string(),
+                    kind
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn cmp() {
+        assert_eq!(
+            _sorted(Kind::Int, &["100", "20", "-3", "0"]),
+            ["-3", "0", "20", "100"]
+        );
+        assert_eq!(
+            _sorted(Kind::Float, &["1e2", "-0.5", "20", "3.25"]),
+            ["-0.5", "3.25", "20", "100"]
+        );
+        assert_eq!(
+            _sorted(
+                Kind::Decimal,
+                &["10.5", "-2", "0.50", "-10.25", "9.99", "-0", "1"]
+            ),
+            ["-10.25", "-2", "0", "0.5", "1", "9.99", "10.5"]
+        );
+        assert_eq!(
+            _sorted(Kind::Date, &["2024-01-10", "1999-12-31", "2024-01-02"]),
+            ["1999-12-31", "2024-01-02", "2024-01-10"]
+        );
+        assert_eq!(
+            _sorted(
+                Kind::Timestamp,
+                &[
+                    "2024-01-01T10:00:00+02:00",
+                    "2024-01-01T09:00:00Z",
+                    "1960-06-01T00:00:00.25"
+                ]
+            ),
+            [
+                "1960-06-01T00:00:00.250000Z",
+                "2024-01-01T08:00:00Z",
+                "2024-01-01T09:00:00Z"
+            ]
+        );
+    }
+
+    #[test]
+    fn encode() {
+        for (kind, text) in [
+            (Kind::Int, "-300"),
+            (Kind::UInt, "18446744073709551615"),
+            (Kind::Float, "-2.5"),
+            (Kind::Date, "1900-03-01"),
+            (Kind::Timestamp, "2024-02-29T23:59:59.999999Z"),
+            (Kind::Decimal, "-0012.3400"),
+            (Kind::Str, "São Paulo"),
+        ] {
+            let value = kind.parse(text).unwrap();
+
+            let mut buf = Vec::new();
+            value.encode(&mut buf);
+
+            let decoded = Value::decode(&mut Reader::create(&buf)).unwrap();
+            assert_eq!(decoded, value);
+            assert_eq!(decoded.to_string(), value.to_string());
+        }
+
+        let mut buf = Vec::new();
+        Kind::Decimal.encode(&mut buf);
+        assert_eq!(
+            Kind::decode(&mut Reader::create(&buf)).unwrap(),
+            Kind::Decimal
+        );
+        assert!(Value::decode(&mut Reader::create(&[9])).is_err());
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!("i64".parse(), Ok(Kind::Int));
+        assert_eq!("timestamp".parse(), Ok(Kind::Timestamp));
+        assert!("i32".parse::<Kind>().is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use crate::error::Result;
+use crate::index::encoding::Checksum;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::io::{BufWriter, ErrorKind};
+
+const MAGIC: &[u8; 8] = b"CSVWAL\0\0";
+const COMMIT: &[u8; 8] = b"COMMIT\0\0";
+const HEADER: usize = 12;
+const TRAILER: usize = 24;
+
+pub type Frame = (u64, Vec<u8>);
+
+pub fn filename(index: &str) -> String {
+    format!("{}.wal", index)
+}
+
+pub fn write(path: &str, page_size: usize, pages: &[Frame]) -> Result<()> {
+    let file = File::create(path)?;
+    let mut writer = BufWriter::new(&file);
+    let mut checksum = Checksum::create();
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&(page_size as u32).to_le_bytes())?;
+
+    for (page, buf) in pages {
+        let page = page.to_le_bytes();
+        checksum.update(&page);
+        checksum.update(buf);
+
+        writer.write_all(&page)?;
+        writer.write_all(buf)?;
+    }
+
+    writer.write_all(COMMIT)?;
+    writer.write_all(&(pages.len() as u64).to_le_bytes())?;
+    writer.write_all(&checksum.finish().to_le_bytes())?;
+    writer.flush()?;
+    drop(writer);
+
+    file.sync_all()?;
+    Ok(())
+}
+
+pub fn read(path: &str) -> Result<Option<Vec<Frame>>> {
+    let buf = match fs::read(path) {
+        Ok(buf) => buf,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+
+    if buf.len() < HEADER + TRAILER || &buf[0..8] != MAGIC {
+        return Ok(None);
+    }
+
+    let page_size = u32::from_le_bytes(buf[8..12].try_into().unwrap()) as usize;
+    let (frames, trailer) = buf[HEADER..].split_at(buf.len() - HEADER - TRAILER);
+
+    let count = u64::from_le_bytes(trailer[8..16].try_into().unwrap());
+    let expected = u64::from_le_bytes(trailer[16..24].try_into().unwrap());
+
+    if &trailer[0..8] != COMMIT || frames.len() as u64 != count * (8 + page_size as u64) {
+        return Ok(None);
+    }
+
+    let mut checksum = Checksum::create();
+    checksum.update(frames);
+
+    if checksum.finish() != expected {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        frames
+            .chunks(8 + page_size)
+            .map(|frame| {
+                let page = u64::from_le_bytes(frame[0..8].try_into().unwrap());
+                (page, frame[8..].to_vec())
+            })
+            .collect(),
+    ))
+}
+
+pub fn remove(path: &str) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(error) if error.kind() != ErrorKind::NotFound => Err(error.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read() {
+        let path = "wal_test_read.wal";
+        let pages = vec![(3, vec![1; 16]), (0, vec![2; 16])];
+
+        assert!(super::read(path).unwrap().is_none());
+
+        write(path, 16, &pages).unwrap();
+        assert_eq!(super::read(path).unwrap().unwrap(), pages);
+
+        let mut buf = fs::read(path).unwrap();
+        buf[HEADER + 8] = 9;
+        fs::write(path, &buf).unwrap();
+        assert!(super::read(path).unwrap().is_none());
+
+        write(path, 16, &pages).unwrap();
+        let buf = fs::read(path).unwrap();
+        fs::write(path, &buf[..buf.len() - 1]).unwrap();
+        assert!(super::read(path).unwrap().is_none());
+
+        remove(path).unwrap();
+        remove(path).unwrap();
+    }
+}
@@ -0,0 +1,20 @@
+#![warn(unnameable_types)]
+
+pub mod csv;
+pub(crate) mod error;
+pub(crate) mod index;
+pub(crate) mod table;
+
+pub use error::{Error, Result};
+pub use index::bplus::{BPlusTree, LeafRange};
+pub use index::btree::{BTree, Conflict, DEFAULT_FILL_FACTOR};
+pub use index::cache::{Capacity, Stats};
+pub use index::key::{Components, Key};
+pub use index::lock::Wait;
+pub use index::range::Range;
+pub use index::schema::{Column, Duplicates, Invalid, Schema};
+pub use index::shared::SharedTree;
+pub use index::snapshot::Snapshot;
+pub use index::source::Source;
+pub use index::value::{Kind, ParseError, Value};
+pub use table::Index;
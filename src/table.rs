@@ -0,0 +1,199 @@
+use crate::csv;
+use crate::error::{Error, Result};
+use crate::index::btree::{BTree, Conflict};
+use crate::index::key::Components;
+use crate::index::schema::Schema;
+use std::fs::{self, File};
+use std::io;
+use std::ops::RangeBounds;
+
+pub struct Index {
+    tree: BTree,
+    file: File,
+}
+
+impl Index {
+    fn attach(tree: BTree) -> Result<Index> {
+        let file = match tree.source() {
+            Some(source) => File::open(&source.path)?,
+            None => {
+                return Err(Error::Io(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "index has no source file",
+                )))
+            }
+        };
+
+        Ok(Index { tree, file })
+    }
+
+    pub fn open(path: &str) -> Result<Index> {
+        Index::attach(csv::open(path, false)?)
+    }
+
+    pub fn open_or_rebuild(path: &str) -> Result<Index> {
+        Index::attach(csv::open(path, true)?)
+    }
+
+    pub fn build(source: &str, path: &str, order: usize, schema: Schema) -> Result<Index> {
+        Index::build_reporting(source, path, order, schema).map(|(index, _)| index)
+    }
+
+    pub fn build_reporting(
+        source: &str,
+        path: &str,
+        order: usize,
+        schema: Schema,
+    ) -> Result<(Index, Vec<Conflict>)> {
+        let mut tree = BTree::create_with_schema(order, path, schema)?;
+
+        // A failed build removes what it created, so it can simply be retried.
+        match csv::build(source, &mut tree) {
+            Ok(conflicts) => Ok((Index::attach(tree)?, conflicts)),
+            Err(error) => {
+                drop(tree);
+                fs::remove_dir_all(path)?;
+                Err(error)
+            }
+        }
+    }
+
+    pub fn update(&mut self) -> Result<Vec<Conflict>> {
+        let path = match self.tree.source() {
+            Some(source) => source.path.clone(),
+            None => return Ok(Vec::new()),
+        };
+
+        let conflicts = csv::index_file_incremental(&path, &mut self.tree)?;
+        self.file = File::open(&path)?;
+
+        Ok(conflicts)
+    }
+
+    pub fn tree(&self) -> &BTree {
+        &self.tree
+    }
+
+    pub fn get<C: Components>(
+        &mut self,
+        key: C,
+    ) -> Result<impl Iterator<Item = Result<String>> + '_> {
+        self.tree.check_source()?;
+
+        let positions: Vec<(u64, u64)> = match self.tree.lookup(key)? {
+            Some(key) => key.positions().collect(),
+            None => Vec::new(),
+        };
+
+        let file = &mut self.file;
+        Ok(positions
+            .into_iter()
+            .map(move |position| csv::read_line(file, position)))
+    }
+
+    pub fn range<'a, R: RangeBounds<&'a str>>(
+        &mut self,
+        bounds: R,
+    ) -> Result<impl Iterator<Item = Result<String>> + '_> {
+        csv::read_range(&mut self.file, &self.tree, bounds)
+    }
+
+    pub fn prefix<'a>(
+        &'a mut self,
+        prefix: &'a str,
+    ) -> Result<impl Iterator<Item = Result<String>> + 'a> {
+        csv::read_prefix(&mut self.file, &self.tree, prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::schema::Duplicates;
+    use std::path::Path;
+
+    #[test]
+    fn build() {
+        let path = "table_test_build";
+        let mut index =
+            Index::build("resources/header.csv", path, 3, Schema::name("city")).unwrap();
+
+        let lines: Vec<String> = index.get("Porto").unwrap().map(Result::unwrap).collect();
+        assert_eq!(lines, ["3,Carla,Porto\n"]);
+        assert_eq!(index.get("Faro").unwrap().count(), 0);
+
+        let lines: Vec<String> = index
+            .range("Lisbon".."Q")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(lines, ["2,Ana,Lisbon\n", "3,Carla,Porto\n"]);
+
+        let lines: Vec<String> = index.prefix("Po").unwrap().map(Result::unwrap).collect();
+        assert_eq!(lines, ["3,Carla,Porto\n"]);
+        assert_eq!(index.prefix("Pa").unwrap().count(), 0);
+
+        drop(index);
+
+        let mut index = Index::open(path).unwrap();
+        assert_eq!(index.tree().schema(), &Schema::name("city"));
+        assert_eq!(index.get("Lisbon").unwrap().count(), 1);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn build_retry() {
+        let source = "table_test_build_retry.csv";
+        let path = "table_test_build_retry";
+
+        assert!(matches!(
+            Index::build(source, path, 3, Schema::default()),
+            Err(Error::Io(_))
+        ));
+        assert!(!Path::new(path).exists());
+
+        fs::write(source, "Lisbon,Ana\nPorto,Bruno\nLisbon,Carla\n").unwrap();
+        let schema = Schema::default().unique(Duplicates::Fail);
+
+        assert!(matches!(
+            Index::build(source, path, 3, schema),
+            Err(Error::Duplicate(_))
+        ));
+        assert!(!Path::new(path).exists());
+
+        let mut index = Index::build(source, path, 3, Schema::default()).unwrap();
+        assert_eq!(index.get("Lisbon").unwrap().count(), 2);
+
+        drop(index);
+        fs::remove_file(source).unwrap();
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn update() {
+        let source = "table_test_update.csv";
+        let path = "table_test_update";
+        fs::write(source, "city,name\nLisbon,Ana\n").unwrap();
+
+        let mut index = Index::build(source, path, 3, Schema::name("city")).unwrap();
+        fs::write(source, "city,name\nLisbon,Ana\nPorto,Bruno\n").unwrap();
+
+        assert!(matches!(index.get("Porto"), Err(Error::Stale(_))));
+        assert!(matches!(Index::open(path), Err(Error::Stale(_))));
+
+        index.update().unwrap();
+
+        let lines: Vec<String> = index.get("Porto").unwrap().map(Result::unwrap).collect();
+        assert_eq!(lines, ["Porto,Bruno\n"]);
+        drop(index);
+
+        fs::write(source, "city,name\nLisbon,Ana\nPorto,Bruno\nFaro,Carla\n").unwrap();
+
+        let mut index = Index::open_or_rebuild(path).unwrap();
+        assert_eq!(index.get("Faro").unwrap().count(), 1);
+
+        fs::remove_file(source).unwrap();
+        fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -0,0 +1,90 @@
+use csv_indexer::{
+    csv, BPlusTree, BTree, Capacity, Column, Components, Conflict, Duplicates, Error, Index, Key,
+    Kind, LeafRange, Range, Schema, SharedTree, Snapshot, Source, Stats, Value, Wait,
+    DEFAULT_FILL_FACTOR,
+};
+use std::fs::{self, File};
+
+// Compiled outside the crate, so every type a public signature uses has to be
+// nameable from here.
+fn _count<C: Components>(tree: &BTree, value: C) -> usize {
+    tree.lookup(value)
+        .unwrap()
+        .map_or(0, |key| key.positions().count())
+}
+
+#[test]
+fn btree() {
+    let path = "api_test_btree";
+    let source = "api_test_btree.csv";
+    fs::write(source, "id,city\n1,Lisbon\n2,Porto\n").unwrap();
+
+    let schema = Schema::name("city").unique(Duplicates::Report);
+    let mut tree = BTree::create_with_schema(3, path, schema).unwrap();
+    tree.set_lock_wait(Wait::NoWait);
+    tree.set_cache_capacity(Capacity::Nodes(16));
+
+    let conflicts: Vec<Conflict> =
+        csv::index_file(&File::open(source).unwrap(), &mut tree).unwrap();
+    assert!(conflicts.is_empty());
+
+    tree.set_source(Source::read(source).unwrap()).unwrap();
+    fs::write(source, "id,city\n1,Lisbon\n2,Porto\n3,Faro\n").unwrap();
+    csv::index_file_incremental(source, &mut tree).unwrap();
+
+    let snapshot: Snapshot = tree.snapshot().unwrap();
+    let range: Range = snapshot.range("Faro"..="Lisbon").unwrap();
+    assert_eq!(range.count(), 2);
+    drop(snapshot);
+
+    assert_eq!(_count(&tree, "Faro"), 1);
+    let stats: Stats = tree.cache_stats();
+    assert!(stats.entries <= 16);
+
+    drop(tree);
+    fs::remove_file(source).unwrap();
+    fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn index() {
+    let path = "api_test_index";
+    let schema = Schema::composite(vec![Column::Position(0)], false).typed(vec![Kind::Int]);
+    let mut index = Index::build("resources/sample.csv", path, 3, schema).unwrap();
+
+    let lines: Vec<String> = index.get("40").unwrap().map(Result::unwrap).collect();
+    assert_eq!(lines, ["40,50,60\n"]);
+    assert_eq!(index.range("50"..).unwrap().count(), 1);
+
+    drop(index);
+    fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn trees() {
+    let bplus_path = "api_test_trees_bplus";
+    let shared_path = "api_test_trees_shared";
+
+    let mut bplus = BPlusTree::create(2, bplus_path).unwrap();
+    bplus
+        .insert(Key::composite(vec![Value::from("A")], (0, 2)))
+        .unwrap();
+    let range: LeafRange = bplus.iter();
+    assert_eq!(range.count(), 1);
+
+    let shared = SharedTree::create(BTree::create(2, shared_path).unwrap());
+    shared.insert(Key::create("A", (0, 2))).unwrap();
+    assert!(shared.lookup("A").unwrap().is_some());
+
+    let mut tree = shared.write().unwrap();
+    assert!(matches!(
+        tree.bulk_load(Vec::<csv_indexer::Result<Key>>::new(), DEFAULT_FILL_FACTOR),
+        Err(Error::NotEmpty(_))
+    ));
+    drop(tree);
+
+    drop(bplus);
+    drop(shared);
+    fs::remove_dir_all(bplus_path).unwrap();
+    fs::remove_dir_all(shared_path).unwrap();
+}